@@ -4,7 +4,7 @@ use core::ptr;
 
 use crate::gpio::*;
 use crate::rcc::{Enable, Rcc};
-use crate::stm32::ADC;
+use crate::stm32::{ADC, SYSCFG};
 
 pub trait Channel<ADC> {
     type ID;
@@ -43,6 +43,18 @@ pub enum Precision {
     B_6 = 0b11,
 }
 
+impl Precision {
+    /// Returns the resolution in bits
+    pub fn bits(self) -> u8 {
+        match self {
+            Precision::B_12 => 12,
+            Precision::B_10 => 10,
+            Precision::B_8 => 8,
+            Precision::B_6 => 6,
+        }
+    }
+}
+
 /// ADC Sampling time
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -128,19 +140,24 @@ pub struct Adc {
 pub struct CalibrationFactor(pub u8);
 
 impl Adc {
+    /// Enables the ADC and its voltage regulator, then runs the calibration
     pub fn new(adc: ADC, rcc: &mut Rcc) -> Self {
         // Enable ADC clocks
         ADC::enable(rcc);
 
         adc.cr().modify(|_, w| w.advregen().set_bit());
+        // tADCVREG_SETUP is 20us
+        cortex_m::asm::delay(rcc.clocks.sys_clk.raw() / 50_000 + 1);
 
-        Self {
+        let mut adc = Self {
             rb: adc,
             sample_time: SampleTime::T_2,
             align: Align::Right,
             precision: Precision::B_12,
             vref_cache: None,
-        }
+        };
+        adc.calibrate();
+        adc
     }
 
     /// Sets ADC source
@@ -160,13 +177,17 @@ impl Adc {
 
     /// Runs the calibration routine on the ADC
     ///
-    /// Wait for tADCVREG_SETUP (20us on STM32G071x8) after calling [`Self::new()`] before calibrating, to wait for the
-    /// ADC voltage regulator to stabilize.
+    /// [`Self::new()`] already calibrates the ADC, call this again after VDDA or temperature changes.
+    /// Calibration requires ADEN=0, so the ADC is disabled first if needed.
     ///
     /// Do not call if an ADC reading is ongoing.
     pub fn calibrate(&mut self) {
+        if self.rb.cr().read().aden().bit_is_set() {
+            self.power_down();
+        }
         self.rb.cr().modify(|_, w| w.adcal().set_bit());
         while self.rb.cr().read().adcal().bit_is_set() {}
+        self.rb.isr().write(|w| w.eocal().clear_bit_by_one());
     }
 
     /// Returns the calibration factors used by the ADC
@@ -191,6 +212,17 @@ impl Adc {
         self.rb.calfact().write(|w| w.calfact().set(calfact.0));
     }
 
+    /// Enables or disables the I/O analog switch voltage booster (SYSCFG BOOSTEN)
+    ///
+    /// The ADC input multiplexer switches have a high resistance when VDDA is
+    /// below 2.4 V, which shows up as wrong readings at short sampling times.
+    /// The booster lowers that resistance and should be enabled in this case.
+    pub fn set_analog_switch_booster(&mut self, enable: bool, rcc: &mut Rcc) {
+        SYSCFG::enable(rcc);
+        let syscfg = unsafe { &(*SYSCFG::ptr()) };
+        syscfg.cfgr1().modify(|_, w| w.boosten().bit(enable));
+    }
+
     /// Set the Adc sampling time
     pub fn set_sample_time(&mut self, t_samp: SampleTime) {
         self.sample_time = t_samp;
@@ -201,7 +233,8 @@ impl Adc {
         self.align = align;
     }
 
-    /// Set the Adc precision
+    /// Set the Adc precision. Each 2 bits less save 2 ADC clock cycles per
+    /// conversion, `read` returns values of the selected resolution.
     pub fn set_precision(&mut self, precision: Precision) {
         self.precision = precision;
     }
@@ -237,6 +270,54 @@ impl Adc {
         self.rb.ier().modify(|_, w| w.eocie().clear_bit()); // end of sequence interupt disable
     }
 
+    /// Configures a single conversion of `pin` started by every rising edge of `trigger`
+    ///
+    /// The end of conversion interrupt is enabled, use [`Self::read_triggered()`] from the
+    /// ADC interrupt handler to fetch the result. The ADC stays powered until
+    /// [`Self::stop_triggered()`] is called.
+    pub fn configure_triggered_single<PIN: Channel<Adc, ID = u8>>(
+        &mut self,
+        pin: &mut PIN,
+        trigger: InjTrigSource,
+    ) {
+        self.rb.cfgr1().modify(|_, w| w.cont().clear_bit());
+        self.prepare_injected(pin, trigger);
+
+        self.rb.isr().modify(|_, w| w.eoc().clear_bit_by_one());
+        self.rb.ier().modify(|_, w| w.eocie().set_bit());
+        // Arm the ADC, conversions start on the next trigger edge
+        self.rb.cr().modify(|_, w| w.adstart().set_bit());
+    }
+
+    /// Reads the latest triggered conversion result
+    ///
+    /// Reading the data register clears the end of conversion flag.
+    pub fn read_triggered(&mut self) -> nb::Result<u16, Infallible> {
+        if self.rb.isr().read().eoc().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let res = self.rb.dr().read().bits() as u16;
+        if self.align == Align::Left && self.precision == Precision::B_6 {
+            Ok(res << 8)
+        } else {
+            Ok(res)
+        }
+    }
+
+    /// Stops triggered conversions and powers down the ADC
+    pub fn stop_triggered(&mut self) {
+        if self.rb.cr().read().adstart().bit_is_set() {
+            self.rb.cr().modify(|_, w| w.adstp().set_bit());
+            while self.rb.cr().read().adstp().bit_is_set() {}
+        }
+        self.rb.ier().modify(|_, w| w.eocie().clear_bit());
+        self.rb
+            .cfgr1()
+            .modify(|_, w| unsafe { w.exten().bits(0b00) });
+        self.power_down();
+    }
+
     /// Read actual VREF voltage using the internal reference
     ///
     /// If oversampling is enabled, the return value is scaled down accordingly.
@@ -258,9 +339,12 @@ impl Adc {
             ptr::read_volatile(0x1FFF_75AA as *const u16) as u32
         };
 
+        // VREFINT_CAL is a 12 bit value
+        let vref_val = (vref_val as u32) << (12 - self.precision.bits());
+
         // RM0454 14.9 Calculating the actual VDDA voltage using the internal reference voltage
         // V_DDA = 3 V x VREFINT_CAL / VREFINT_DATA
-        let vref = (vref_cal * 3_000_u32 / vref_val as u32) as u16;
+        let vref = (vref_cal * 3_000_u32 / vref_val) as u16;
         self.vref_cache = Some(vref);
         Ok(vref)
     }
@@ -276,6 +360,19 @@ impl Adc {
         }
     }
 
+    /// Reads the VBAT voltage in mV
+    ///
+    /// VBAT is measured through its internal 1/3 divider and scaled with the VREFINT based VDDA
+    /// reading. The bridge is only enabled for the measurement to avoid draining the battery.
+    /// The channel needs a sampling time of at least 12 us, see `set_sample_time`.
+    pub fn read_vbat(&mut self) -> nb::Result<u16, Infallible> {
+        let mut vbat = VBat::new();
+        vbat.enable(self);
+        let result = self.read_voltage(&mut vbat);
+        vbat.disable(self);
+        result.map(|mv| mv.saturating_mul(3))
+    }
+
     pub fn read_voltage<PIN: Channel<Adc, ID = u8>>(
         &mut self,
         pin: &mut PIN,
@@ -283,7 +380,7 @@ impl Adc {
         let vref = self.get_vref_cached()?;
 
         self.read(pin).map(|raw| {
-            let adc_mv = (vref as u32 * raw as u32) >> 12;
+            let adc_mv = (vref as u32 * raw as u32) >> self.precision.bits();
             adc_mv as u16
         })
     }
@@ -323,6 +420,67 @@ impl Adc {
         Ok(val)
     }
 
+    /// Converts all `channels` in a single sequencer scan and returns the
+    /// results in the order given
+    ///
+    /// Channels are numbers as returned by [`Channel::channel()`]. The
+    /// sequencer converts them in ascending channel order, each channel once.
+    ///
+    /// # Panics
+    ///
+    /// Panics for channel numbers above 18.
+    pub fn read_all<const N: usize>(&mut self, channels: &[u8; N]) -> [u16; N] {
+        let mut by_channel = [0u16; 19];
+        assert!(
+            channels.iter().all(|&ch| (ch as usize) < by_channel.len()),
+            "ADC channel out of range"
+        );
+        let mask = channels.iter().fold(0u32, |mask, ch| mask | (1 << ch));
+
+        self.power_up();
+        self.rb.cfgr1().modify(|_, w| unsafe {
+            w.res()
+                .bits(self.precision as u8)
+                .align()
+                .bit(self.align == Align::Left)
+                .scandir()
+                .clear_bit()
+        });
+
+        self.rb
+            .smpr()
+            .modify(|_, w| w.smp1().set(self.sample_time as u8));
+
+        self.rb.chselr0().modify(|_, w| unsafe { w.bits(mask) });
+
+        self.rb.isr().modify(|_, w| {
+            w.eoc()
+                .clear_bit_by_one()
+                .eos()
+                .clear_bit_by_one()
+                .ovr()
+                .clear_bit_by_one()
+        });
+        self.rb.cr().modify(|_, w| w.adstart().set_bit());
+
+        for (ch, res) in by_channel.iter_mut().enumerate() {
+            if mask & (1 << ch) == 0 {
+                continue;
+            }
+            while self.rb.isr().read().eoc().bit_is_clear() {}
+            let val = self.rb.dr().read().bits() as u16;
+            *res = if self.align == Align::Left && self.precision == Precision::B_6 {
+                val << 8
+            } else {
+                val
+            };
+        }
+        while self.rb.isr().read().eos().bit_is_clear() {}
+
+        self.power_down();
+        channels.map(|ch| by_channel[ch as usize])
+    }
+
     pub fn read_temperature(&mut self) -> nb::Result<i16, Infallible> {
         let mut vtemp = VTemp::new();
         let vtemp_voltage: u16 = if vtemp.enabled(self) {
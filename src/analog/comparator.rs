@@ -522,7 +522,7 @@ impl ComparatorSplit for COMP {
     }
 }
 
-pub trait OutputPin<COMP> {
+pub trait OutputPin<COMP>: crate::Sealed {
     fn setup(&self);
     fn release(self) -> Self;
 }
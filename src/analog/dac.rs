@@ -13,6 +13,15 @@ pub trait DacOut<V> {
     fn get_value(&mut self) -> V;
 }
 
+/// Simultaneous update of both DAC channels
+pub trait DualDacOut<V> {
+    /// Writes both channels through the dual data holding register, so they
+    /// latch on the same trigger
+    fn set_dual(&mut self, ch1: V, ch2: V);
+    /// Software triggers both channels at once
+    fn trigger_dual(&mut self);
+}
+
 pub struct GeneratorConfig {
     mode: u8,
     amp: u8,
@@ -34,6 +43,29 @@ impl GeneratorConfig {
     }
 }
 
+/// Sample and hold timing, in LSI clock cycles
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SampleHoldConfig {
+    sample: u16,
+    hold: u16,
+    refresh: u8,
+}
+
+impl SampleHoldConfig {
+    /// `sample` and `hold` are 10 bit values. The output is sampled for
+    /// `sample` cycles, then held for `hold` cycles before each refresh of
+    /// `refresh` cycles.
+    pub fn new(sample: u16, hold: u16, refresh: u8) -> Self {
+        assert!(sample < 1024 && hold < 1024);
+        Self {
+            sample,
+            hold,
+            refresh,
+        }
+    }
+}
+
 /// Enabled DAC (type state)
 pub struct Enabled;
 /// Enabled DAC without output buffer (type state)
@@ -49,6 +81,11 @@ impl ED for EnabledUnbuffered {}
 impl ED for WaveGenerator {}
 impl ED for Disabled {}
 
+/// Enabled DAC states driving the pin, with or without output buffer
+pub trait EnabledOutput: ED {}
+impl EnabledOutput for Enabled {}
+impl EnabledOutput for EnabledUnbuffered {}
+
 pub struct Channel1<ED> {
     _enabled: PhantomData<ED>,
 }
@@ -99,7 +136,13 @@ macro_rules! dac {
         $wave:ident,
         $mamp:ident,
         $ten:ident,
-        $swtrig:ident
+        $tsel:ident,
+        $swtrig:ident,
+        $shsrx:ident,
+        $tsample:ident,
+        $thold:ident,
+        $trefresh:ident,
+        $bwst:ident
     ),)+) => {
         $(
             impl $CX<Disabled> {
@@ -178,6 +221,35 @@ macro_rules! dac {
                     }
                 }
 
+                /// Latches written values only on a software trigger, e.g.
+                /// `DualDacOut::trigger_dual` for synchronized updates
+                pub fn enable_software_trigger(&mut self) {
+                    let dac = unsafe { &(*DAC::ptr()) };
+                    let enabled = dac.cr().read().$en().bit_is_set();
+                    // TSELx and TENx may only be changed while the channel is disabled
+                    dac.cr().modify(|_, w| w.$en().clear_bit());
+                    dac.cr().modify(|_, w| unsafe { w.$tsel().bits(0).$ten().set_bit() });
+                    dac.cr().modify(|_, w| w.$en().bit(enabled));
+                }
+
+                /// Latches written values immediately
+                pub fn disable_trigger(&mut self) {
+                    let dac = unsafe { &(*DAC::ptr()) };
+                    let enabled = dac.cr().read().$en().bit_is_set();
+                    dac.cr().modify(|_, w| w.$en().clear_bit());
+                    dac.cr().modify(|_, w| w.$ten().clear_bit());
+                    dac.cr().modify(|_, w| w.$en().bit(enabled));
+                }
+
+                fn set_mode(&mut self, mode: u8) {
+                    let dac = unsafe { &(*DAC::ptr()) };
+                    let enabled = dac.cr().read().$en().bit_is_set();
+                    // MODEx may only be changed while the channel is disabled
+                    dac.cr().modify(|_, w| w.$en().clear_bit());
+                    dac.mcr().modify(|_, w| unsafe { w.$mode().bits(mode) });
+                    dac.cr().modify(|_, w| w.$en().bit(enabled));
+                }
+
                 /// Disable the DAC channel
                 pub fn disable(self) -> $CX<Disabled> {
                     let dac = unsafe { &(*DAC::ptr()) };
@@ -191,6 +263,60 @@ macro_rules! dac {
                 }
             }
 
+            impl $CX<Enabled> {
+                /// Disables the output buffer. The channel stays connected to
+                /// its pin and keeps its sample and hold setting.
+                pub fn disable_output_buffer(mut self) -> $CX<EnabledUnbuffered> {
+                    let dac = unsafe { &(*DAC::ptr()) };
+                    let mode = dac.mcr().read().$mode().bits() & 0b100;
+                    self.set_mode(mode | 0b010);
+
+                    $CX {
+                        _enabled: PhantomData,
+                    }
+                }
+            }
+
+            impl $CX<EnabledUnbuffered> {
+                /// Enables the output buffer. The channel stays connected to
+                /// its pin and keeps its sample and hold setting.
+                pub fn enable_output_buffer(mut self) -> $CX<Enabled> {
+                    let dac = unsafe { &(*DAC::ptr()) };
+                    let mode = dac.mcr().read().$mode().bits() & 0b100;
+                    self.set_mode(mode | 0b001);
+
+                    $CX {
+                        _enabled: PhantomData,
+                    }
+                }
+            }
+
+            impl<ED: EnabledOutput> $CX<ED> {
+                /// Switches the channel to sample and hold mode. The output
+                /// keeps its level from the hold capacitor, refreshed from the
+                /// LSI clock, so it also runs in Stop mode. Enables the LSI.
+                pub fn sample_hold(&mut self, config: SampleHoldConfig, rcc: &mut Rcc) {
+                    let dac = unsafe { &(*DAC::ptr()) };
+                    rcc.enable_lsi();
+                    let enabled = dac.cr().read().$en().bit_is_set();
+                    dac.cr().modify(|_, w| w.$en().clear_bit());
+                    while dac.sr().read().$bwst().bit_is_set() {}
+                    dac.$shsrx().write(|w| unsafe { w.$tsample().bits(config.sample) });
+                    dac.shhr().modify(|_, w| unsafe { w.$thold().bits(config.hold) });
+                    dac.shrr().modify(|_, w| unsafe { w.$trefresh().bits(config.refresh) });
+                    dac.cr().modify(|_, w| w.$en().bit(enabled));
+                    let mode = dac.mcr().read().$mode().bits() | 0b100;
+                    self.set_mode(mode);
+                }
+
+                /// Leaves sample and hold mode
+                pub fn disable_sample_hold(&mut self) {
+                    let dac = unsafe { &(*DAC::ptr()) };
+                    let mode = dac.mcr().read().$mode().bits() & !0b100;
+                    self.set_mode(mode);
+                }
+            }
+
             /// DacOut implementation available in any Enabled/Disabled
             /// state
             impl<ED> DacOut<u16> for $CX<ED> {
@@ -216,6 +342,21 @@ macro_rules! dac {
     };
 }
 
+/// Available once both channels are enabled
+impl<ED1: EnabledOutput, ED2: EnabledOutput> DualDacOut<u16> for (Channel1<ED1>, Channel2<ED2>) {
+    fn set_dual(&mut self, ch1: u16, ch2: u16) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.dhr12rd()
+            .write(|w| unsafe { w.dacc1dhr().bits(ch1).dacc2dhr().bits(ch2) });
+    }
+
+    fn trigger_dual(&mut self) {
+        let dac = unsafe { &(*DAC::ptr()) };
+        dac.swtrgr()
+            .write(|w| w.swtrig1().set_bit().swtrig2().set_bit());
+    }
+}
+
 pub trait DacExt {
     fn constrain<PINS>(self, pins: PINS, rcc: &mut Rcc) -> PINS::Output
     where
@@ -245,7 +386,13 @@ dac!(
             wave1,
             mamp1,
             ten1,
-            swtrig1
+            tsel1,
+            swtrig1,
+            shsr1,
+            tsample1,
+            thold1,
+            trefresh1,
+            bwst1
         ),
     Channel2:
         (
@@ -260,6 +407,12 @@ dac!(
             wave2,
             mamp2,
             ten2,
-            swtrig2
+            tsel2,
+            swtrig2,
+            shsr2,
+            tsample2,
+            thold2,
+            trefresh2,
+            bwst2
         ),
 );
@@ -82,6 +82,14 @@ pub trait ExtiExt {
     fn unlisten(&self, ev: Event);
     fn is_pending(&self, ev: Event, edge: SignalEdge) -> bool;
     fn unpend(&self, ev: Event);
+    /// Returns a bit mask of the GPIO lines (0..15) with a pending edge event
+    fn pending_lines(&self) -> u16;
+    /// Clears the pending edge events of a GPIO line
+    fn clear(&self, line: u8);
+    /// Returns the pending GPIO lines selected by `mask` and clears them.
+    /// Useful to fan out the shared EXTI0_1, EXTI2_3 and EXTI4_15 vectors,
+    /// e.g. `exti.take_pending(0xfff0)` inside the EXTI4_15 handler.
+    fn take_pending(&self, mask: u16) -> u16;
 }
 
 impl ExtiExt for EXTI {
@@ -196,4 +204,21 @@ impl ExtiExt for EXTI {
             self.fpr1().modify(|_, w| unsafe { w.bits(1 << line) });
         }
     }
+
+    fn pending_lines(&self) -> u16 {
+        (self.rpr1().read().bits() | self.fpr1().read().bits()) as u16
+    }
+
+    fn clear(&self, line: u8) {
+        assert!(line < 16);
+        self.rpr1().write(|w| unsafe { w.bits(1 << line) });
+        self.fpr1().write(|w| unsafe { w.bits(1 << line) });
+    }
+
+    fn take_pending(&self, mask: u16) -> u16 {
+        let pending = self.pending_lines() & mask;
+        self.rpr1().write(|w| unsafe { w.bits(pending as u32) });
+        self.fpr1().write(|w| unsafe { w.bits(pending as u32) });
+        pending
+    }
 }
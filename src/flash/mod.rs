@@ -17,6 +17,68 @@ pub const NUM_PAGES: u32 = 64;
 
 const FLASH_KEY1: u32 = 0x4567_0123;
 const FLASH_KEY2: u32 = 0xCDEF_89AB;
+const OPT_KEY1: u32 = 0x0819_2A3B;
+const OPT_KEY2: u32 = 0x4C5D_6E7F;
+
+/// Boot configuration held in the option bytes
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BootOptions {
+    /// nBOOT0 value, used as BOOT0 when `n_boot_sel` is set
+    pub n_boot0: bool,
+    /// nBOOT1 value
+    pub n_boot1: bool,
+    /// When set BOOT0 is taken from `n_boot0` instead of the BOOT0 pin
+    pub n_boot_sel: bool,
+}
+
+/// Current option byte configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OptionBytes {
+    /// Boot configuration
+    pub boot: BootOptions,
+    /// Read protection level byte
+    pub rdp: u8,
+    /// Boot is forced into main flash
+    #[cfg(not(feature = "stm32g0x0"))]
+    pub boot_lock: bool,
+}
+
+/// Reads the option bytes loaded at the last option byte load
+pub fn read_option_bytes() -> OptionBytes {
+    // Safe, the option registers are only read
+    let flash = unsafe { &*FLASH::ptr() };
+    let optr = flash.optr().read();
+    OptionBytes {
+        boot: BootOptions {
+            n_boot0: optr.n_boot0().bit_is_set(),
+            n_boot1: optr.n_boot1().bit_is_set(),
+            n_boot_sel: optr.n_boot_sel().bit_is_set(),
+        },
+        rdp: optr.rdp().bits(),
+        #[cfg(not(feature = "stm32g0x0"))]
+        boot_lock: flash.secr().read().boot_lock().bit_is_set(),
+    }
+}
+
+/// Confirmation required to program option bytes
+///
+/// Wrong option bytes can make the device unbootable or unreachable by the
+/// debugger, so creating this token is `unsafe`.
+pub struct OptionBytesWriteConfirm {
+    _private: (),
+}
+
+impl OptionBytesWriteConfirm {
+    /// # Safety
+    ///
+    /// The caller takes responsibility for the option byte values written by
+    /// [`UnlockedFlash::program_option_bytes`].
+    pub unsafe fn new() -> Self {
+        OptionBytesWriteConfirm { _private: () }
+    }
+}
 
 impl FlashPage {
     /// This gives the starting address of a flash page in physical address
@@ -239,6 +301,46 @@ impl WriteErase for UnlockedFlash {
 }
 
 impl UnlockedFlash {
+    /// Programs the boot option bytes and reloads them.
+    ///
+    /// All other option bytes, including read protection, are kept. The option
+    /// byte load resets the device, so this only returns on failure.
+    pub fn program_option_bytes(
+        &mut self,
+        boot: BootOptions,
+        _confirm: OptionBytesWriteConfirm,
+    ) -> Result {
+        self.wait()?;
+        self.clear_errors();
+
+        // Unlock option bytes
+        self.f
+            .optkeyr()
+            .write(|w| unsafe { w.optkey().bits(OPT_KEY1) });
+        self.f
+            .optkeyr()
+            .write(|w| unsafe { w.optkey().bits(OPT_KEY2) });
+        if self.f.cr().read().optlock().bit_is_set() {
+            return Err(Error::Failure);
+        }
+
+        self.f.optr().modify(|_, w| {
+            w.n_boot0()
+                .bit(boot.n_boot0)
+                .n_boot1()
+                .bit(boot.n_boot1)
+                .n_boot_sel()
+                .bit(boot.n_boot_sel)
+        });
+        self.f.cr().modify(|_, w| w.optstrt().set_bit());
+        self.wait()?;
+
+        self.f.cr().modify(|_, w| w.obl_launch().set_bit());
+        // The device is reset by the option byte load
+        self.wait()?;
+        Err(Error::Failure)
+    }
+
     fn clear_errors(&mut self) {
         self.f.sr().modify(|_, w| {
             w.progerr().set_bit();
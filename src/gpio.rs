@@ -1,7 +1,7 @@
 //! General Purpose Input / Output
 use core::marker::PhantomData;
 
-use crate::rcc::Rcc;
+use crate::rcc::{Enable, Rcc};
 use core::convert::Infallible;
 use hal::digital::{ErrorType, InputPin, OutputPin, PinState, StatefulOutputPin};
 
@@ -52,6 +52,14 @@ pub struct Output<MODE> {
 /// Push pull output (type state)
 pub struct PushPull;
 
+/// Whole GPIO port, used to drive several pins of the port at once.
+///
+/// Only the pins selected by a mask are touched, the pins still have to be
+/// configured as outputs through their own handles.
+pub struct GpioPort<GPIO> {
+    _gpio: PhantomData<GPIO>,
+}
+
 /// Fully erased pin
 pub struct Pin<MODE> {
     i: u8,
@@ -158,6 +166,15 @@ pub enum Speed {
     VeryHigh = 3,
 }
 
+/// GPIO pull resistor selection
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pull {
+    None = 0,
+    Up = 1,
+    Down = 2,
+}
+
 /// Trigger edge
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -197,6 +214,30 @@ macro_rules! gpio {
                 $(
                     pub $pxi: $PXi<DefaultMode>,
                 )+
+                pub port: GpioPort<$GPIOX>,
+            }
+
+            impl GpioPort<$GPIOX> {
+                /// Sets the pins selected by `mask` to the matching bits of
+                /// `value` with a single atomic BSRR write
+                pub fn write_bits(&mut self, mask: u16, value: u16) {
+                    let set = (mask & value) as u32;
+                    let reset = (mask & !value) as u32;
+                    // NOTE(unsafe) atomic write to a stateless register
+                    unsafe { (*$GPIOX::ptr()).bsrr().write(|w| w.bits(reset << 16 | set)) };
+                }
+
+                /// Reads the input levels of all pins of the port
+                pub fn read_bits(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$GPIOX::ptr()).idr().read().bits() as u16 }
+                }
+
+                /// Reads the output levels of all pins of the port
+                pub fn read_output_bits(&self) -> u16 {
+                    // NOTE(unsafe) atomic read with no side effects
+                    unsafe { (*$GPIOX::ptr()).odr().read().bits() as u16 }
+                }
             }
 
             impl GpioExt for $GPIOX {
@@ -209,6 +250,7 @@ macro_rules! gpio {
                         $(
                             $pxi: $PXi { _mode: PhantomData },
                         )+
+                        port: GpioPort { _gpio: PhantomData },
                     }
                 }
             }
@@ -285,6 +327,8 @@ macro_rules! gpio {
                     _mode: PhantomData<MODE>,
                 }
 
+                impl<MODE> crate::Sealed for $PXi<MODE> {}
+
                 #[allow(clippy::from_over_into)]
                 impl Into<$PXi<Input<PullDown>>> for $PXi<DefaultMode> {
                     fn into(self) -> $PXi<Input<PullDown>> {
@@ -352,6 +396,11 @@ macro_rules! gpio {
                     }
 
                     /// Configures the pin to operate as an analog pin
+                    ///
+                    /// Pull resistors are disabled and the ADC/DAC/COMP path is
+                    /// connected by the analog mode itself; the G0 has no per-pin
+                    /// analog switch register. With VDDA below 2.4 V enable the
+                    /// analog switch booster, see `Adc::set_analog_switch_booster`.
                     pub fn into_analog(self) -> $PXi<Analog> {
                         unsafe {
                             let gpio = &(*$GPIOX::ptr());
@@ -399,6 +448,23 @@ macro_rules! gpio {
                         $PXi { _mode: PhantomData }
                     }
 
+                    /// Temporarily drives the pin as a push pull output while
+                    /// `f` runs, then restores its mode and output type. The
+                    /// alternate function selection is kept.
+                    pub fn with_push_pull_output<R>(&mut self, f: impl FnOnce(&mut $PXi<Output<PushPull>>) -> R) -> R {
+                        let gpio = unsafe { &(*$GPIOX::ptr()) };
+                        let moder = gpio.moder().read().bits();
+                        let otyper = gpio.otyper().read().bits();
+                        gpio.otyper().modify(|_, w| w.ot($i).push_pull());
+                        gpio.moder().modify(|_, w| w.moder($i).output());
+                        let result = f(&mut $PXi { _mode: PhantomData });
+                        let mask = 1 << $i;
+                        gpio.otyper().modify(|r, w| unsafe { w.bits((r.bits() & !mask) | (otyper & mask)) });
+                        let mask = 0b11 << ($i * 2);
+                        gpio.moder().modify(|r, w| unsafe { w.bits((r.bits() & !mask) | (moder & mask)) });
+                        result
+                    }
+
                     /// Configures the pin as external trigger
                     pub fn listen(self, edge: SignalEdge, exti: &mut EXTI) -> $PXi<Input<Floating>> {
                         unsafe {
@@ -429,6 +495,10 @@ macro_rules! gpio {
                     }
 
                     /// Set pin speed
+                    ///
+                    /// The G0 GPIOs have no drive strength setting, OSPEEDR only
+                    /// controls the slew rate. The Fast-mode Plus pins (PA9, PA10,
+                    /// PB6 – PB9) offer a high current sink via `set_high_drive`.
                     pub fn set_speed(self, speed: Speed) -> Self {
                         unsafe {
                             (*$GPIOX::ptr()).ospeedr().modify(|_, w| w.ospeedr($i).bits(speed as u8));
@@ -436,6 +506,18 @@ macro_rules! gpio {
                         self
                     }
 
+                    /// Changes the pull resistor in place, without touching the pin mode.
+                    ///
+                    /// The type state is not updated, so e.g. a `Input<PullUp>` pin
+                    /// keeps its type after switching to `Pull::Down`.
+                    pub fn set_pull(&mut self, pull: Pull) {
+                        unsafe {
+                            (*$GPIOX::ptr()).pupdr().modify(|_, w| w.pupdr($i).bits(pull as u8));
+                        }
+                    }
+
+                    /// Only called from the sealed peripheral pin traits, whose
+                    /// impls list the documented alternate function of each pin
                     #[allow(dead_code)]
                     pub(crate) fn set_alt_mode(&self, mode: AltFunction) {
                         unsafe {
@@ -697,3 +779,28 @@ gpio!(GPIOF, gpiof, PF, 5, [
     PF14: (pf14, 14),
     PF15: (pf15, 15),
 ]);
+
+macro_rules! fast_mode_plus {
+    ($($PXi:ident: $fmp:ident,)+) => {
+        $(
+            impl<MODE> $PXi<MODE> {
+                /// Enables the Fast-mode Plus high current sink (20 mA) of the
+                /// pin, e.g. to drive an indicator LED directly
+                pub fn set_high_drive(&mut self, enable: bool, rcc: &mut Rcc) {
+                    <crate::stm32::SYSCFG as Enable>::enable(rcc);
+                    let syscfg = unsafe { &(*crate::stm32::SYSCFG::ptr()) };
+                    syscfg.cfgr1().modify(|_, w| w.$fmp().bit(enable));
+                }
+            }
+        )+
+    }
+}
+
+fast_mode_plus! {
+    PA9: i2c_pa9_fmp,
+    PA10: i2c_pa10_fmp,
+    PB6: i2c_pbx_fmp,
+    PB7: i2c_pb7_fmp,
+    PB8: i2c_pb8_fmp,
+    PB9: i2c_pb9_fmp,
+}
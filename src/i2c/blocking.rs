@@ -1,12 +1,14 @@
 //! I2C
-use super::config::Config;
+use super::config::{Config, TimingError};
 use super::{
     Error, I2c, I2cDirection, I2cExt, I2cPeripheral, I2cPeripheralEvent, Instance, SCLPin, SDAPin,
 };
+use crate::dma::{Channel, Direction, WordSize};
 use crate::gpio::*;
 use crate::i2c;
 use crate::rcc::*;
 use crate::stm32 as pac;
+use hal::i2c::{NoAcknowledgeSource, Operation};
 
 pub trait I2cSlave {
     /// Enable/Disable Slave Byte Control. Default SBC is switched on.
@@ -63,6 +65,7 @@ macro_rules! flush_rxdr {
 /// In all other case the macro will return without a result
 macro_rules! busy_wait {
     ($i2c:expr, $flag:ident, $variant:ident, $idx:ident, $buflen:ident) => {
+        let mut nacked = false;
         loop {
             let isr = $i2c.isr().read();
 
@@ -76,6 +79,7 @@ macro_rules! busy_wait {
                 return Err(Error::ArbitrationLost);
             } else if isr.nackf().bit_is_set() {
                 $i2c.icr().write(|w| w.nackcf().set_bit());
+                nacked = true;
                 // Make one extra loop to wait on the stop condition
             } else if isr.tcr().bit_is_set() {
                 // This condition Will only happen when reload == 1 and sbr == 1 (slave) and nbytes was written.
@@ -101,7 +105,10 @@ macro_rules! busy_wait {
                     return Ok(())
                 } else
                 if $idx == 0 {
-                    return Err(Error::Nack)
+                    return Err(Error::Nack(NoAcknowledgeSource::Address))
+                } else
+                if nacked {
+                    return Err(Error::Nack(NoAcknowledgeSource::Data))
                 } else
                 {
                   return Err(Error::IncorrectFrameSize($idx))
@@ -165,6 +172,24 @@ where
     SDA: SDAPin<I2C>,
     SCL: SCLPin<I2C>,
 {
+    /// Like `new`, but computes the bus timing from the requested SCL
+    /// frequency and returns an error if it is not achievable
+    pub fn try_new(
+        i2c: I2C,
+        sda: SDA,
+        scl: SCL,
+        config: impl Into<Config>,
+        rcc: &mut Rcc,
+    ) -> Result<Self, TimingError> {
+        let config = config.into();
+        let timing = config.try_timing_bits(super::kernel_clock::<I2C>(&config, rcc))?;
+        let config = Config {
+            timing: Some(timing),
+            ..config
+        };
+        Ok(Self::new(i2c, sda, scl, config, rcc))
+    }
+
     pub fn new(i2c: I2C, sda: SDA, scl: SCL, config: impl Into<Config>, rcc: &mut Rcc) -> Self
     where
         SDA: SDAPin<I2C>,
@@ -178,13 +203,14 @@ where
         i2c.cr1().modify(|_, w| w.pe().clear_bit());
 
         // Setup protocol timings
-        let timing_bits = config.timing_bits(rcc.clocks.apb_clk);
+        let timing_bits = config.timing_bits(super::kernel_clock::<I2C>(&config, rcc));
         i2c.timingr().write(|w| unsafe { w.bits(timing_bits) });
 
         // Enable the I2C processing
         i2c.cr1().modify(|_, w| {
             w.pe().set_bit();
             w.dnf().set(config.digital_filter);
+            w.wupen().bit(config.wakeup_from_stop);
             w.anfoff().bit(!config.analog_filter)
         });
 
@@ -243,6 +269,8 @@ where
 }
 
 impl<I2C: Instance, SDA, SCL> I2c<I2C, SDA, SCL> {
+    /// Writes `snd_buffer`, then reads `rcv_buffer` after a repeated START,
+    /// without a STOP in between
     pub fn write_read(
         &mut self,
         addr: u8,
@@ -323,6 +351,21 @@ impl<I2C: Instance, SDA, SCL> I2c<I2C, SDA, SCL> {
     }
 }
 
+impl<I2C: Instance, SDA, SCL> I2c<I2C, SDA, SCL> {
+    /// Like [`Self::write_read`], but ends the write with a STOP and starts
+    /// the read with a new START, for devices that do not accept a repeated
+    /// START
+    pub fn write_then_read_with_stop(
+        &mut self,
+        addr: u8,
+        snd_buffer: &[u8],
+        rcv_buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        self.write(addr, snd_buffer)?;
+        self.read(addr, rcv_buffer)
+    }
+}
+
 impl<I2C: Instance, SDA, SCL> I2c<I2C, SDA, SCL> {
     pub fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
         let buflen = bytes.len();
@@ -400,6 +443,169 @@ impl<I2C: Instance, SDA, SCL> I2c<I2C, SDA, SCL> {
     }
 }
 
+/// I2C master transfer driven by a DMA channel, started with
+/// [`I2c::write_dma`] or [`I2c::read_dma`]
+pub struct DmaTransfer<'a, I2C, SDA, SCL, CH, BUF> {
+    i2c: &'a mut I2c<I2C, SDA, SCL>,
+    channel: &'a mut CH,
+    buffer: BUF,
+    len: usize,
+    // bytes not yet announced to the peripheral in NBYTES
+    pending: usize,
+    nacked: bool,
+}
+
+impl<I2C: Instance, SDA, SCL> I2c<I2C, SDA, SCL> {
+    /// Writes `bytes` to `addr` using `channel`. Transfers longer than 255
+    /// bytes are split in NBYTES reloads, up to 65535 bytes in total.
+    pub fn write_dma<'a, CH: Channel>(
+        &'a mut self,
+        addr: u8,
+        bytes: &'static [u8],
+        channel: &'a mut CH,
+    ) -> DmaTransfer<'a, I2C, SDA, SCL, CH, &'static [u8]> {
+        let (ptr, len) = (bytes.as_ptr() as u32, bytes.len());
+        self.start_dma(addr, ptr, len, false, channel);
+        DmaTransfer::new(self, channel, bytes, len)
+    }
+
+    /// Reads `bytes.len()` bytes from `addr` using `channel`. Transfers
+    /// longer than 255 bytes are split in NBYTES reloads, up to 65535 bytes
+    /// in total.
+    pub fn read_dma<'a, CH: Channel>(
+        &'a mut self,
+        addr: u8,
+        bytes: &'static mut [u8],
+        channel: &'a mut CH,
+    ) -> DmaTransfer<'a, I2C, SDA, SCL, CH, &'static mut [u8]> {
+        let (ptr, len) = (bytes.as_mut_ptr() as u32, bytes.len());
+        self.start_dma(addr, ptr, len, true, channel);
+        DmaTransfer::new(self, channel, bytes, len)
+    }
+
+    fn start_dma<CH: Channel>(
+        &mut self,
+        addr: u8,
+        memory: u32,
+        len: usize,
+        read: bool,
+        channel: &mut CH,
+    ) {
+        assert!(len > 0 && len <= u16::MAX as usize);
+
+        // Wait for any previous address sequence to end automatically.
+        while self.i2c.cr2().read().start().bit_is_set() {}
+
+        channel.disable();
+        if read {
+            let _ = self.i2c.rxdr().read().rxdata().bits();
+            channel.set_peripheral_address(self.i2c.rxdr().as_ptr() as u32, false);
+            channel.set_direction(Direction::FromPeripheral);
+            channel.select_peripheral(I2C::dmamux_rx());
+        } else {
+            self.i2c.isr().write(|w| w.txe().set_bit());
+            channel.set_peripheral_address(self.i2c.txdr().as_ptr() as u32, false);
+            channel.set_direction(Direction::FromMemory);
+            channel.select_peripheral(I2C::dmamux_tx());
+        }
+        channel.set_memory_address(memory, true);
+        channel.set_transfer_length(len as u16);
+        channel.set_word_size(WordSize::BITS8);
+        channel.set_circular_mode(false);
+        channel.enable();
+
+        self.i2c
+            .cr1()
+            .modify(|_, w| w.rxdmaen().bit(read).txdmaen().bit(!read));
+
+        let reload = len > 255;
+        self.i2c.cr2().modify(|_, w| {
+            w.nbytes().set(len.min(255) as u8);
+            w.sadd().set((addr << 1) as u16);
+            w.add10().clear_bit();
+            w.rd_wrn().bit(read);
+            w.autoend().bit(!reload);
+            w.reload().bit(reload);
+            w.start().set_bit()
+        });
+    }
+}
+
+impl<'a, I2C: Instance, SDA, SCL, CH: Channel, BUF> DmaTransfer<'a, I2C, SDA, SCL, CH, BUF> {
+    fn new(i2c: &'a mut I2c<I2C, SDA, SCL>, channel: &'a mut CH, buffer: BUF, len: usize) -> Self {
+        DmaTransfer {
+            i2c,
+            channel,
+            buffer,
+            len,
+            pending: len - len.min(255),
+            nacked: false,
+        }
+    }
+
+    /// Services NBYTES reloads and returns true once the STOP condition was
+    /// sent. Call this until it returns true or an error, then [`Self::wait`].
+    pub fn is_done(&mut self) -> Result<bool, Error> {
+        let i2c = &self.i2c.i2c;
+        let isr = i2c.isr().read();
+        if isr.berr().bit_is_set() {
+            i2c.icr().write(|w| w.berrcf().set_bit());
+            Err(Error::BusError)
+        } else if isr.arlo().bit_is_set() {
+            i2c.icr().write(|w| w.arlocf().set_bit());
+            Err(Error::ArbitrationLost)
+        } else if isr.nackf().bit_is_set() {
+            // The STOP condition follows automatically
+            i2c.icr().write(|w| w.nackcf().set_bit());
+            self.nacked = true;
+            Ok(false)
+        } else if isr.stopf().bit_is_set() {
+            flush_txdr!(i2c);
+            i2c.icr().write(|w| w.stopcf().set_bit());
+            let done = self.len - self.channel.get_transfer_remaining() as usize;
+            if self.nacked && done == 0 {
+                Err(Error::Nack(NoAcknowledgeSource::Address))
+            } else if self.nacked {
+                Err(Error::Nack(NoAcknowledgeSource::Data))
+            } else if self.pending > 0 {
+                Err(Error::IncorrectFrameSize(done))
+            } else {
+                Ok(true)
+            }
+        } else if isr.tcr().bit_is_set() {
+            let chunk = self.pending.min(255);
+            self.pending -= chunk;
+            let reload = self.pending > 0;
+            i2c.cr2().modify(|_, w| {
+                w.nbytes().set(chunk as u8);
+                w.autoend().bit(!reload);
+                w.reload().bit(reload)
+            });
+            Ok(false)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Blocks until the transfer has ended, stops the DMA channel and
+    /// returns the buffer together with the result
+    pub fn wait(mut self) -> (Result<(), Error>, BUF) {
+        let result = loop {
+            match self.is_done() {
+                Ok(true) => break Ok(()),
+                Ok(false) => {}
+                Err(err) => break Err(err),
+            }
+        };
+        self.channel.disable();
+        self.i2c
+            .i2c
+            .cr1()
+            .modify(|_, w| w.rxdmaen().clear_bit().txdmaen().clear_bit());
+        (result, self.buffer)
+    }
+}
+
 impl<I2C: Instance, SDA, SCL> I2cSlave for I2c<I2C, SDA, SCL> {
     fn slave_sbc(&mut self, sbc_enabled: bool) {
         // Enable Slave byte control
@@ -506,16 +712,89 @@ impl<I2C: Instance, SDA, SCL> hal::i2c::I2c for I2c<I2C, SDA, SCL> {
         address: hal::i2c::SevenBitAddress,
         operations: &mut [hal::i2c::Operation<'_>],
     ) -> Result<(), Self::Error> {
-        for op in operations {
+        let mut start = 0;
+        while start < operations.len() {
+            // adjacent operations of the same direction form one transfer
+            let read = matches!(operations[start], Operation::Read(_));
+            let end = operations[start..]
+                .iter()
+                .position(|op| matches!(op, Operation::Read(_)) != read)
+                .map_or(operations.len(), |n| start + n);
+            let last = end == operations.len();
+            self.transfer_operations(address, &mut operations[start..end], read, last)?;
+            start = end;
+        }
+        Ok(())
+    }
+}
+
+impl<I2C: Instance, SDA, SCL> I2c<I2C, SDA, SCL> {
+    /// Runs `operations`, all reading or all writing, as a single transfer.
+    /// It starts with a START, or a repeated START after a previous transfer
+    /// of the transaction. The `last` transfer ends with a STOP.
+    fn transfer_operations(
+        &mut self,
+        addr: u8,
+        operations: &mut [Operation<'_>],
+        read: bool,
+        last: bool,
+    ) -> Result<(), Error> {
+        let buflen: usize = operations
+            .iter()
+            .map(|op| match op {
+                Operation::Read(buffer) => buffer.len(),
+                Operation::Write(buffer) => buffer.len(),
+            })
+            .sum();
+        // TODO support transfers of more than 255 bytes
+        assert!(buflen < 256 && buflen > 0);
+
+        // Wait for any previous address sequence to end automatically.
+        // This could be up to 50% of a bus cycle (ie. up to 0.5/freq)
+        while self.i2c.cr2().read().start().bit_is_set() {}
+        if read {
+            flush_rxdr!(self.i2c);
+        } else {
+            self.i2c.isr().write(|w| w.txe().set_bit());
+        }
+
+        self.i2c.cr2().write(|w| {
+            // Set number of bytes to transfer
+            w.nbytes().set(buflen as u8);
+            // Set address to transfer to/from
+            w.sadd().set((addr << 1) as u16);
+            // 7-bit addressing mode
+            w.add10().clear_bit();
+            w.rd_wrn().bit(read);
+            // Automatic end mode for the last transfer only
+            w.autoend().bit(last);
+            w.reload().clear_bit();
+            // Start transfer
+            w.start().set_bit()
+        });
+
+        let mut idx = 0;
+        for op in operations.iter_mut() {
             match op {
-                hal::i2c::Operation::Read(buffer) => {
-                    self.read(address, buffer)?;
+                Operation::Write(bytes) => {
+                    for byte in bytes.iter() {
+                        busy_wait!(self.i2c, txis, bit_is_set, idx, buflen);
+                        self.i2c.txdr().write(|w| w.txdata().set(*byte));
+                        idx += 1;
+                    }
                 }
-                hal::i2c::Operation::Write(buffer) => {
-                    self.write(address, buffer)?;
+                Operation::Read(bytes) => {
+                    for byte in bytes.iter_mut() {
+                        busy_wait!(self.i2c, rxne, bit_is_set, idx, buflen);
+                        *byte = self.i2c.rxdr().read().rxdata().bits();
+                        idx += 1;
+                    }
                 }
             }
         }
+        // The last transfer returns on the STOP condition, the others end
+        // with TC, ready for the repeated START
+        busy_wait!(self.i2c, tc, bit_is_set, idx, buflen);
         Ok(())
     }
 }
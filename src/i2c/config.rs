@@ -11,6 +11,7 @@ pub struct Config {
     pub address_11bits: bool,
     pub slave_address_2: u8,
     pub slave_address_mask: SlaveAddressMask,
+    pub wakeup_from_stop: bool,
 }
 
 impl Config {
@@ -24,6 +25,7 @@ impl Config {
             address_11bits: false,
             slave_address_2: 0,
             slave_address_mask: SlaveAddressMask::MaskNone,
+            wakeup_from_stop: false,
         }
     }
 
@@ -37,6 +39,7 @@ impl Config {
             address_11bits: false,
             slave_address_2: 0,
             slave_address_mask: SlaveAddressMask::MaskNone,
+            wakeup_from_stop: false,
         }
     }
 
@@ -51,6 +54,16 @@ impl Config {
         self
     }
 
+    /// Wakes the MCU from Stop mode on an own address match (I2C1 only).
+    ///
+    /// The peripheral is clocked from HSI16, which is also used for the
+    /// bus timing. The digital filter must stay disabled.
+    pub fn wakeup_from_stop(mut self) -> Self {
+        assert!(self.digital_filter == 0);
+        self.wakeup_from_stop = true;
+        self
+    }
+
     pub fn timing_bits(&self, i2c_clk: Hertz) -> u32 {
         if let Some(bits) = self.timing {
             return bits;
@@ -74,6 +87,16 @@ impl Config {
         psc << 28 | scldel << 20 | sdadel << 16 | sclh << 8 | scll
     }
 
+    /// Returns the TIMINGR value for this configuration. If no explicit
+    /// timing was given, it is computed with `compute_timing`.
+    pub fn try_timing_bits(&self, i2c_clk: Hertz) -> Result<u32, TimingError> {
+        match (self.timing, self.speed) {
+            (Some(bits), _) => Ok(bits),
+            (None, Some(speed)) => compute_timing(i2c_clk, speed),
+            (None, None) => Err(TimingError::SpeedTooLow),
+        }
+    }
+
     /// Slave address 1 as 7 bit address, in range 0 .. 127
     pub fn slave_address(&mut self, own_address: u8) {
         self.slave_address_1 = own_address as u16;
@@ -99,3 +122,117 @@ impl From<Hertz> for Config {
         Config::new(speed)
     }
 }
+
+/// I2C timing computation error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimingError {
+    /// Requested SCL frequency is above Fast-mode Plus or not reachable with the I2C clock
+    SpeedTooHigh,
+    /// Requested SCL frequency is too low for the I2C clock
+    SpeedTooLow,
+    /// I2C clock is too slow for the requested bus mode
+    ClockTooLow,
+}
+
+/// Bus timing limits from the I2C specification, all values in nanoseconds
+struct BusTimings {
+    min_clk: u32,
+    low_min: u32,
+    high_min: u32,
+    su_dat_min: u32,
+    rise_max: u32,
+    fall_max: u32,
+}
+
+impl BusTimings {
+    fn for_speed(scl: Hertz) -> Result<Self, TimingError> {
+        match scl.raw() {
+            0 => Err(TimingError::SpeedTooLow),
+            // Standard-mode
+            1..=100_000 => Ok(BusTimings {
+                min_clk: 2_000_000,
+                low_min: 4_700,
+                high_min: 4_000,
+                su_dat_min: 250,
+                rise_max: 1_000,
+                fall_max: 300,
+            }),
+            // Fast-mode
+            100_001..=400_000 => Ok(BusTimings {
+                min_clk: 9_000_000,
+                low_min: 1_300,
+                high_min: 600,
+                su_dat_min: 100,
+                rise_max: 300,
+                fall_max: 300,
+            }),
+            // Fast-mode Plus
+            400_001..=1_000_000 => Ok(BusTimings {
+                min_clk: 19_000_000,
+                low_min: 500,
+                high_min: 260,
+                su_dat_min: 50,
+                rise_max: 120,
+                fall_max: 120,
+            }),
+            _ => Err(TimingError::SpeedTooHigh),
+        }
+    }
+}
+
+/// Computes the TIMINGR register value (PRESC, SCLDEL, SDADEL, SCLH, SCLL)
+/// for the requested SCL frequency, following the reference manual
+/// algorithm with the analog filter enabled and the digital filter off.
+pub fn compute_timing(i2c_clk: Hertz, scl: Hertz) -> Result<u32, TimingError> {
+    const PS_PER_S: u64 = 1_000_000_000_000;
+
+    let timings = BusTimings::for_speed(scl)?;
+    if i2c_clk.raw() < timings.min_clk {
+        return Err(TimingError::ClockTooLow);
+    }
+
+    let clk = i2c_clk.raw() as u64;
+    let t_clk = PS_PER_S / clk;
+    // SCL period, minus the rise and fall times which are not generated by the peripheral
+    let period = (PS_PER_S / scl.raw() as u64)
+        .saturating_sub((timings.rise_max + timings.fall_max) as u64 * 1_000);
+
+    for presc in 0..16u64 {
+        let t_presc = (presc + 1) * PS_PER_S / clk;
+        let cycles = period / t_presc;
+        if cycles > 512 {
+            continue;
+        }
+
+        let low_min = (timings.low_min as u64 * 1_000).div_ceil(t_presc);
+        let high_min = (timings.high_min as u64 * 1_000).div_ceil(t_presc);
+        if cycles < low_min + high_min {
+            return Err(TimingError::SpeedTooHigh);
+        }
+
+        // Share the spare cycles equally between the low and high periods
+        let spare = cycles - low_min - high_min;
+        let low = cmp::min(low_min + spare / 2, 256);
+        let high = cycles - low;
+        if high > 256 {
+            continue;
+        }
+
+        let scldel = ((timings.rise_max + timings.su_dat_min) as u64 * 1_000)
+            .div_ceil(t_presc)
+            .saturating_sub(1);
+        // Data hold time has to cover the fall time, minus the input synchronization delay
+        let sdadel = (timings.fall_max as u64 * 1_000)
+            .saturating_sub(3 * t_clk)
+            .div_ceil(t_presc);
+        if scldel > 15 || sdadel > 15 {
+            continue;
+        }
+
+        let timing = presc << 28 | scldel << 20 | sdadel << 16 | (high - 1) << 8 | (low - 1);
+        return Ok(timing as u32);
+    }
+
+    Err(TimingError::SpeedTooLow)
+}
@@ -9,8 +9,10 @@ pub use nonblocking::*;
 
 pub mod config;
 
+use crate::dmamux::DmaMuxIndex;
 use crate::rcc::{self, Rcc};
-pub use config::Config;
+use crate::time::Hertz;
+pub use config::{Config, TimingError};
 use hal::i2c::{ErrorKind, NoAcknowledgeSource};
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -62,7 +64,8 @@ pub enum Event {
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Error {
     Overrun,
-    Nack,
+    /// The address or a data byte was not acknowledged
+    Nack(NoAcknowledgeSource),
     PECError,
     BusError,
     ArbitrationLost,
@@ -75,7 +78,7 @@ impl hal::i2c::Error for Error {
             Error::Overrun => ErrorKind::Overrun,
             Error::BusError => ErrorKind::Bus,
             Error::ArbitrationLost => ErrorKind::ArbitrationLoss,
-            Error::Nack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            Error::Nack(source) => ErrorKind::NoAcknowledge(*source),
             _ => ErrorKind::Other,
         }
     }
@@ -87,19 +90,66 @@ pub trait Instance:
     + rcc::Enable
     + rcc::Reset
 {
+    /// Clocks the peripheral from HSI16 so it can wake the MCU from Stop.
+    /// Returns false if this instance has no wakeup support.
+    fn select_wakeup_clock(_rcc: &mut Rcc) -> bool {
+        false
+    }
+
+    /// DMAMUX request of the receive data register
+    fn dmamux_rx() -> DmaMuxIndex;
+
+    /// DMAMUX request of the transmit data register
+    fn dmamux_tx() -> DmaMuxIndex;
+}
+
+impl Instance for crate::stm32::I2C1 {
+    fn select_wakeup_clock(rcc: &mut Rcc) -> bool {
+        rcc.enable_hsi();
+        rcc.ccipr().modify(|_, w| w.i2c1sel().hsi16());
+        true
+    }
+
+    fn dmamux_rx() -> DmaMuxIndex {
+        DmaMuxIndex::I2C1_RX
+    }
+
+    fn dmamux_tx() -> DmaMuxIndex {
+        DmaMuxIndex::I2C1_TX
+    }
+}
+impl Instance for crate::stm32::I2C2 {
+    fn dmamux_rx() -> DmaMuxIndex {
+        DmaMuxIndex::I2C2_RX
+    }
+
+    fn dmamux_tx() -> DmaMuxIndex {
+        DmaMuxIndex::I2C2_TX
+    }
 }
 
-impl Instance for crate::stm32::I2C1 {}
-impl Instance for crate::stm32::I2C2 {}
+/// Returns the I2C kernel clock for `config`, switching to HSI16 if the
+/// peripheral has to wake up the MCU from Stop
+fn kernel_clock<I2C: Instance>(config: &Config, rcc: &mut Rcc) -> Hertz {
+    if config.wakeup_from_stop {
+        assert!(
+            I2C::select_wakeup_clock(rcc),
+            "only I2C1 can wake up from Stop"
+        );
+        Hertz::MHz(16)
+    } else {
+        rcc.clocks.apb_clk
+    }
+}
 
 /// I2C SDA pin
-pub trait SDAPin<I2C> {
+pub trait SDAPin<I2C>: crate::Sealed {
     fn setup(&self);
     fn release(self) -> Self;
 }
 
 /// I2C SCL pin
-pub trait SCLPin<I2C> {
+pub trait SCLPin<I2C>: crate::Sealed {
     fn setup(&self);
     fn release(self) -> Self;
 }
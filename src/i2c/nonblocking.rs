@@ -1,10 +1,11 @@
 //! I2C
-use super::config::Config;
+use super::config::{Config, TimingError};
 use super::{EndMarker, Error, I2c, I2cDirection, I2cExt, I2cResult, Instance, SCLPin, SDAPin};
 use crate::gpio::*;
 use crate::gpio::{AltFunction, OpenDrain, Output};
 use crate::rcc::Rcc;
 use crate::stm32 as pac;
+use hal::i2c::NoAcknowledgeSource;
 use nb::Error::{Other, WouldBlock};
 
 pub trait I2cControl {
@@ -151,6 +152,24 @@ where
     SDA: SDAPin<I2C>,
     SCL: SCLPin<I2C>,
 {
+    /// Like `new`, but computes the bus timing from the requested SCL
+    /// frequency and returns an error if it is not achievable
+    pub fn try_new(
+        i2c: I2C,
+        sda: SDA,
+        scl: SCL,
+        config: impl Into<Config>,
+        rcc: &mut Rcc,
+    ) -> Result<Self, TimingError> {
+        let config = config.into();
+        let timing = config.try_timing_bits(super::kernel_clock::<I2C>(&config, rcc))?;
+        let config = Config {
+            timing: Some(timing),
+            ..config
+        };
+        Ok(Self::new(i2c, sda, scl, config, rcc))
+    }
+
     pub fn new(i2c: I2C, sda: SDA, scl: SCL, config: impl Into<Config>, rcc: &mut Rcc) -> Self
     where
         SDA: SDAPin<I2C>,
@@ -164,13 +183,14 @@ where
         i2c.cr1().modify(|_, w| w.pe().clear_bit());
 
         // Setup protocol timings
-        let timing_bits = config.timing_bits(rcc.clocks.apb_clk);
+        let timing_bits = config.timing_bits(super::kernel_clock::<I2C>(&config, rcc));
         i2c.timingr().write(|w| unsafe { w.bits(timing_bits) });
 
         // Enable the I2C processing
         i2c.cr1().modify(|_, w| unsafe {
             w.pe().set_bit();
             w.dnf().bits(config.digital_filter);
+            w.wupen().bit(config.wakeup_from_stop);
             w.anfoff().bit(!config.analog_filter)
         });
 
@@ -319,7 +339,7 @@ impl<I2C: Instance, SDA, SCL> I2cControl for I2c<I2C, SDA, SCL> {
             self.watchdog = 0;
             if self.index == 0 {
                 self.errors += 1;
-                return Err(Other(Error::Nack));
+                return Err(Other(Error::Nack(NoAcknowledgeSource::Address)));
             } else {
                 // figure out the direction
                 let direction = if isr.dir().bit_is_set() {
@@ -373,7 +393,7 @@ impl<I2C: Instance, SDA, SCL> I2cControl for I2c<I2C, SDA, SCL> {
             } else if self.index == 0 {
                 self.i2c.cr2().modify(|_, w| w.stop().set_bit());
                 self.errors += 1;
-                return Err(Other(Error::Nack));
+                return Err(Other(Error::Nack(NoAcknowledgeSource::Address)));
             } else {
                 self.i2c.cr2().modify(|_, w| w.stop().set_bit());
                 self.errors += 1;
@@ -63,6 +63,7 @@ pub mod rcc;
 pub mod rng;
 pub mod rtc;
 pub mod serial;
+pub mod signature;
 pub mod spi;
 pub mod time;
 pub mod timer;
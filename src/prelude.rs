@@ -34,7 +34,9 @@ pub use crate::rng::RngCore as _;
 pub use crate::rng::RngExt as _;
 pub use crate::rtc::RtcExt as _;
 pub use crate::serial::SerialExt as _;
+pub use crate::spi::ModeExt as _;
 pub use crate::spi::SpiExt as _;
+pub use crate::time::ScaledExt as _;
 pub use crate::time::U32Ext as _;
 pub use crate::timer::opm::OpmExt as _;
 pub use crate::timer::pwm::PwmExt as _;
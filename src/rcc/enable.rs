@@ -4,12 +4,14 @@ macro_rules! bus_enable {
     ($PER:ident => $en:ident) => {
         impl Enable for crate::stm32::$PER {
             #[inline(always)]
-            fn enable(rcc: &mut Rcc) {
-                Self::Bus::enr(rcc).modify(|_, w| w.$en().set_bit());
+            fn enable(_rcc: &mut Rcc) {
+                // NOTE(unsafe) `&mut Rcc` grants exclusive access
+                unsafe { Self::enable_unchecked() }
             }
             #[inline(always)]
-            fn disable(rcc: &mut Rcc) {
-                Self::Bus::enr(rcc).modify(|_, w| w.$en().clear_bit());
+            fn disable(_rcc: &mut Rcc) {
+                // NOTE(unsafe) `&mut Rcc` grants exclusive access
+                unsafe { Self::disable_unchecked() }
             }
             #[inline(always)]
             fn is_enabled() -> bool {
@@ -24,12 +26,19 @@ macro_rules! bus_enable {
             #[inline(always)]
             unsafe fn enable_unchecked() {
                 let rcc = &*RCC::ptr();
-                Self::Bus::enr(rcc).modify(|_, w| w.$en().set_bit());
+                cortex_m::interrupt::free(|_| {
+                    Self::Bus::enr(rcc).modify(|_, w| w.$en().set_bit());
+                });
+                // Delay after the clock enable before the peripheral
+                // registers are accessed, RM0444 5.2.17
+                let _ = Self::Bus::enr(rcc).read();
             }
             #[inline(always)]
             unsafe fn disable_unchecked() {
                 let rcc = &*RCC::ptr();
-                Self::Bus::enr(rcc).modify(|_, w| w.$en().clear_bit());
+                cortex_m::interrupt::free(|_| {
+                    Self::Bus::enr(rcc).modify(|_, w| w.$en().clear_bit());
+                });
             }
         }
     };
@@ -72,15 +81,19 @@ macro_rules! bus_reset {
     ($PER:ident => $rst:ident) => {
         impl Reset for crate::stm32::$PER {
             #[inline(always)]
-            fn reset(rcc: &mut Rcc) {
-                Self::Bus::rstr(rcc).modify(|_, w| w.$rst().set_bit());
-                Self::Bus::rstr(rcc).modify(|_, w| w.$rst().clear_bit());
+            fn reset(_rcc: &mut Rcc) {
+                // NOTE(unsafe) `&mut Rcc` grants exclusive access
+                unsafe { Self::reset_unchecked() }
             }
             #[inline(always)]
             unsafe fn reset_unchecked() {
                 let rcc = &*RCC::ptr();
-                Self::Bus::rstr(rcc).modify(|_, w| w.$rst().set_bit());
-                Self::Bus::rstr(rcc).modify(|_, w| w.$rst().clear_bit());
+                // Both read-modify-writes in one critical section, so an
+                // interrupt touching the same register cannot interleave
+                cortex_m::interrupt::free(|_| {
+                    Self::Bus::rstr(rcc).modify(|_, w| w.$rst().set_bit());
+                    Self::Bus::rstr(rcc).modify(|_, w| w.$rst().clear_bit());
+                });
             }
         }
     };
@@ -145,7 +158,6 @@ bus! {
     GPIOF => (IOP, iopfen, iopfsmen, iopfrst), // 5
 }
 
-#[cfg(any(feature = "stm32g030", feature = "stm32g031", feature = "stm32g041"))]
 bus! {
     SYSCFG => (APB2, syscfgen, syscfgsmen, syscfgrst), // 0
 }
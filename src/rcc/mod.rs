@@ -1,4 +1,4 @@
-use crate::stm32::{rcc, FLASH, PWR, RCC};
+use crate::stm32::{rcc, DBG, FLASH, PWR, RCC};
 use crate::time::Hertz;
 use fugit::RateExtU32;
 
@@ -57,6 +57,39 @@ impl Default for Clocks {
     }
 }
 
+impl Clocks {
+    /// Returns the system clock
+    pub fn sysclk(&self) -> Hertz {
+        self.sys_clk
+    }
+
+    /// Returns the AHB clock
+    pub fn hclk(&self) -> Hertz {
+        self.ahb_clk
+    }
+
+    /// Returns the APB clock of the peripherals
+    pub fn pclk(&self) -> Hertz {
+        self.apb_clk
+    }
+
+    /// Returns the APB timer clock, twice `pclk` if the APB prescaler is not 1
+    pub fn pclk_tim(&self) -> Hertz {
+        self.apb_tim_clk
+    }
+
+    /// Returns the asynchronous ADC clock selected by ADCSEL, before the ADC
+    /// prescaler, or 0 Hz if the PLL P output is selected but disabled
+    pub fn adc_clk(&self) -> Hertz {
+        let rcc = unsafe { &(*RCC::ptr()) };
+        match rcc.ccipr().read().adcsel().bits() {
+            0b01 => self.pll_clk.p.unwrap_or(Hertz::from_raw(0)),
+            0b10 => Hertz::from_raw(HSI_FREQ),
+            _ => self.sys_clk,
+        }
+    }
+}
+
 /// Reset pin mode and remap
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -192,6 +225,22 @@ impl Rcc {
             .modify(|_, w| unsafe { w.hsitrim().bits(value) });
     }
 
+    /// Returns the current HSI16 trimming value (0 – 127, reset value 64)
+    pub fn hsi_trim(&self) -> u8 {
+        self.icscr().read().hsitrim().bits()
+    }
+
+    /// Adjusts the HSI16 trimming by `delta` steps and returns the new trimming value
+    ///
+    /// One step is roughly 0.3% of the HSI16 frequency. The result saturates at the ends
+    /// of the trimming range. Combined with [`crate::analog::adc::Adc::read_temperature()`]
+    /// this allows compensating the HSI16 temperature drift at runtime.
+    pub fn trim_hsi(&mut self, delta: i8) -> u8 {
+        let trim = (self.hsi_trim() as i16 + delta as i16).clamp(0, 0x7f) as u8;
+        self.icscr().modify(|_, w| w.hsitrim().set(trim));
+        trim
+    }
+
     #[cfg(not(feature = "stm32g0x0"))]
     pub fn set_reset_mode(&mut self, mode: ResetMode) {
         unsafe {
@@ -301,6 +350,26 @@ impl Rcc {
         while self.csr().read().lsirdy().bit_is_clear() {}
     }
 
+    /// Sets or clears a freeze bit of DBG_APB_FZ1, or DBG_APB_FZ2 if `apb2` is set
+    pub(crate) fn freeze_on_debug(&mut self, apb2: bool, bit: u8, freeze: bool) {
+        self.apbenr1().modify(|_, w| w.dbgen().set_bit());
+        let dbg = unsafe { &(*DBG::ptr()) };
+        let update = |bits: u32| {
+            if freeze {
+                bits | (1 << bit)
+            } else {
+                bits & !(1 << bit)
+            }
+        };
+        if apb2 {
+            dbg.apb_fz2()
+                .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+        } else {
+            dbg.apb_fz1()
+                .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+        }
+    }
+
     pub(crate) fn unlock_rtc(&self) {
         self.apbenr1().modify(|_, w| w.pwren().set_bit());
         let pwr = unsafe { &(*PWR::ptr()) };
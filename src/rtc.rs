@@ -1,7 +1,7 @@
 //! Real Time Clock
 use crate::gpio::*;
 use crate::rcc::{RTCSrc, Rcc};
-use crate::stm32::RTC;
+use crate::stm32::{RTC, TAMP};
 use crate::time::*;
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -106,6 +106,9 @@ impl From<Time> for Alarm {
     }
 }
 
+/// Number of TAMP backup registers
+pub const BACKUP_REGISTERS: usize = 5;
+
 pub struct Rtc {
     rb: RTC,
 }
@@ -305,6 +308,56 @@ impl Rtc {
         });
     }
 
+    /// Starts the periodic wakeup timer and enables its interrupt.
+    ///
+    /// The wakeup timer is clocked by the 1 Hz calendar clock, `period` is
+    /// clamped to 1 s to 36 h. To wake up from Stop mode also unmask the RTC
+    /// EXTI line with `exti.wakeup(exti::Event::RTC)`.
+    pub fn enable_wakeup(&mut self, period: Second) {
+        let secs = period.ticks().clamp(1, 0x2_0000);
+        // WUCKSEL = 0b11x adds 2^16 to the counter reload value
+        let (wucksel, wut) = if secs > 0x1_0000 {
+            (0b110, secs - 0x1_0001)
+        } else {
+            (0b100, secs - 1)
+        };
+
+        self.unprotected(|rb| {
+            rb.cr().modify(|_, w| w.wute().clear_bit());
+            while rb.icsr().read().wutwf().bit_is_clear() {}
+            rb.wutr().write(|w| unsafe { w.wut().bits(wut as u16) });
+            rb.scr().write(|w| w.cwutf().set_bit());
+            rb.cr().modify(|_, w| unsafe {
+                w.wucksel().bits(wucksel).wutie().set_bit().wute().set_bit()
+            });
+        });
+    }
+
+    /// Stops the periodic wakeup timer and disables its interrupt
+    pub fn disable_wakeup(&mut self) {
+        self.unprotected(|rb| {
+            rb.cr()
+                .modify(|_, w| w.wute().clear_bit().wutie().clear_bit());
+        });
+    }
+
+    /// Reads one of the five TAMP backup registers
+    pub fn read_backup_register(&self, n: usize) -> u32 {
+        assert!(n < BACKUP_REGISTERS);
+        let tamp = unsafe { &*TAMP::ptr() };
+        tamp.bkpr(n).read().bkp().bits()
+    }
+
+    /// Writes one of the five TAMP backup registers.
+    ///
+    /// Backup registers keep their value in Stop, Standby and across system
+    /// resets. Backup domain write access is enabled by `Rtc::new`.
+    pub fn write_backup_register(&mut self, n: usize, value: u32) {
+        assert!(n < BACKUP_REGISTERS);
+        let tamp = unsafe { &*TAMP::ptr() };
+        tamp.bkpr(n).write(|w| unsafe { w.bkp().bits(value) });
+    }
+
     pub fn enable_calibration_output<PIN: RtcOutputPin>(
         &mut self,
         pin: PIN,
@@ -327,6 +380,17 @@ impl Rtc {
         });
     }
 
+    /// Runs `closure` with write protection disabled, without entering init mode
+    fn unprotected<F>(&mut self, mut closure: F)
+    where
+        F: FnMut(&mut RTC),
+    {
+        self.rb.wpr().write(|w| unsafe { w.bits(0xCA) });
+        self.rb.wpr().write(|w| unsafe { w.bits(0x53) });
+        closure(&mut self.rb);
+        self.rb.wpr().write(|w| unsafe { w.bits(0xFF) });
+    }
+
     fn modify<F>(&mut self, mut closure: F)
     where
         F: FnMut(&mut RTC),
@@ -360,7 +424,7 @@ impl RtcExt for RTC {
     }
 }
 
-pub trait RtcOutputPin {
+pub trait RtcOutputPin: crate::Sealed {
     fn setup(&self);
     fn channel(&self) -> bool;
     fn release(self) -> Self;
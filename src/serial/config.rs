@@ -69,6 +69,9 @@ pub struct BasicConfig {
     pub(crate) inverted_tx: bool,
     pub(crate) inverted_rx: bool,
     pub(crate) swap: bool,
+    pub(crate) oversampling_8: bool,
+    pub(crate) overrun_disable: bool,
+    pub(crate) driver_enable: DriverEnableConfig,
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -88,6 +91,22 @@ pub struct FullConfig {
     pub(crate) rx_fifo_interrupt: bool,
     #[doc = "Number of bits no activity on rx line"]
     pub(crate) receiver_timeout: Option<u32>,
+    pub(crate) oversampling_8: bool,
+    pub(crate) overrun_disable: bool,
+    pub(crate) driver_enable: DriverEnableConfig,
+}
+
+/// RS-485 driver enable (DE) signal configuration
+///
+/// Only applied when the serial pins include a DE pin.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct DriverEnableConfig {
+    #[doc = "DE assertion time before the start bit, in sample time units"]
+    pub(crate) assertion_time: u8,
+    #[doc = "DE deassertion time after the last stop bit, in sample time units"]
+    pub(crate) deassertion_time: u8,
+    pub(crate) active_low: bool,
 }
 
 impl BasicConfig {
@@ -148,6 +167,37 @@ impl BasicConfig {
         self.swap = true;
         self
     }
+
+    /// Oversample by 8 instead of 16, doubling the maximum baud rate at the
+    /// cost of noise tolerance. Not available on the LPUART.
+    pub fn oversampling_8(mut self) -> Self {
+        self.oversampling_8 = true;
+        self
+    }
+
+    /// Disables overrun detection: a received frame that is not read in time
+    /// is overwritten instead of setting ORE and stalling reception
+    pub fn disable_overrun(mut self) -> Self {
+        self.overrun_disable = true;
+        self
+    }
+
+    /// Sets the RS-485 DE assertion and deassertion times
+    ///
+    /// Times are given in sample time units (1/16 bit with oversampling by 16)
+    /// and must not exceed 31.
+    pub fn driver_enable_timings(mut self, assertion_time: u8, deassertion_time: u8) -> Self {
+        assert!(assertion_time < 32 && deassertion_time < 32);
+        self.driver_enable.assertion_time = assertion_time;
+        self.driver_enable.deassertion_time = deassertion_time;
+        self
+    }
+
+    /// Drives the RS-485 DE signal active low
+    pub fn driver_enable_active_low(mut self) -> Self {
+        self.driver_enable.active_low = true;
+        self
+    }
 }
 
 impl FullConfig {
@@ -209,6 +259,37 @@ impl FullConfig {
         self
     }
 
+    /// Oversample by 8 instead of 16, doubling the maximum baud rate at the
+    /// cost of noise tolerance. Not available on the LPUART.
+    pub fn oversampling_8(mut self) -> Self {
+        self.oversampling_8 = true;
+        self
+    }
+
+    /// Disables overrun detection: a received frame that is not read in time
+    /// is overwritten instead of setting ORE and stalling reception
+    pub fn disable_overrun(mut self) -> Self {
+        self.overrun_disable = true;
+        self
+    }
+
+    /// Sets the RS-485 DE assertion and deassertion times
+    ///
+    /// Times are given in sample time units (1/16 bit with oversampling by 16)
+    /// and must not exceed 31.
+    pub fn driver_enable_timings(mut self, assertion_time: u8, deassertion_time: u8) -> Self {
+        assert!(assertion_time < 32 && deassertion_time < 32);
+        self.driver_enable.assertion_time = assertion_time;
+        self.driver_enable.deassertion_time = deassertion_time;
+        self
+    }
+
+    /// Drives the RS-485 DE signal active low
+    pub fn driver_enable_active_low(mut self) -> Self {
+        self.driver_enable.active_low = true;
+        self
+    }
+
     pub fn fifo_enable(mut self) -> Self {
         self.fifo_enable = true;
         self
@@ -256,6 +337,9 @@ impl Default for BasicConfig {
             inverted_tx: false,
             inverted_rx: false,
             swap: false,
+            oversampling_8: false,
+            overrun_disable: false,
+            driver_enable: DriverEnableConfig::default(),
         }
     }
 }
@@ -277,6 +361,9 @@ impl Default for FullConfig {
             tx_fifo_interrupt: false,
             rx_fifo_interrupt: false,
             receiver_timeout: None,
+            oversampling_8: false,
+            overrun_disable: false,
+            driver_enable: DriverEnableConfig::default(),
         }
     }
 }
@@ -93,19 +93,21 @@ pub struct Serial<USART, Config> {
 }
 
 // Serial TX pin
-pub trait TxPin<USART> {
+pub trait TxPin<USART>: crate::Sealed {
     fn setup(&self);
     fn release(self) -> Self;
 }
 
 // Serial RX pin
-pub trait RxPin<USART> {
+pub trait RxPin<USART>: crate::Sealed {
     fn setup(&self);
     fn release(self) -> Self;
 }
 
 pub struct NoTx;
 
+impl crate::Sealed for NoTx {}
+
 impl<USART> TxPin<USART> for NoTx {
     fn setup(&self) {}
 
@@ -115,6 +117,8 @@ impl<USART> TxPin<USART> for NoTx {
 }
 pub struct NoRx;
 
+impl crate::Sealed for NoRx {}
+
 impl<USART> RxPin<USART> for NoRx {
     fn setup(&self) {}
 
@@ -124,7 +128,7 @@ impl<USART> RxPin<USART> for NoRx {
 }
 
 // Driver enable pin
-pub trait DriverEnablePin<USART> {
+pub trait DriverEnablePin<USART>: crate::Sealed {
     fn setup(&self);
     fn release(self) -> Self;
 }
@@ -175,6 +179,20 @@ where
     }
 }
 
+/// OVER8 bit of CR1, absent from the LPUART register block
+const CR1_OVER8: u32 = 1 << 15;
+
+/// Computes the BRR value. With oversampling by 8 the fraction in BRR\[3:0\]
+/// holds USARTDIV\[3:1\] in BRR\[2:0\].
+fn brr(clk: u64, baudrate: u64, clk_mul: u64, oversampling_8: bool) -> u32 {
+    if oversampling_8 {
+        let div = (2 * clk_mul * clk) / baudrate;
+        ((div & !0xf) | ((div & 0xf) >> 1)) as u32
+    } else {
+        ((clk_mul * clk) / baudrate) as u32
+    }
+}
+
 pub trait SerialExt<CONFIG>: Sized {
     fn usart(
         self,
@@ -374,6 +392,22 @@ macro_rules! uart_shared {
             }
         }
 
+        impl<Config> fmt::Write for Tx<$USARTX, Config> {
+            /// Blocks on every byte and waits for transmission complete at the end
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                for byte in s.bytes() {
+                    block!(self.write(byte)).map_err(|_| fmt::Error)?;
+                }
+                block!(self.flush()).map_err(|_| fmt::Error)
+            }
+        }
+
+        impl<Config> fmt::Write for Serial<$USARTX, Config> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.tx.write_str(s)
+            }
+        }
+
         impl<Config> Serial<$USARTX, Config> {
             pub fn flush(&mut self) -> nb::Result<(), nb::Error<Error>> {
                 self.tx.flush()
@@ -441,7 +475,7 @@ macro_rules! uart_shared {
 
 macro_rules! uart_basic {
     ($USARTX:ident,
-        $usartX:ident, $clk_mul:expr
+        $usartX:ident, $clk_mul:expr, $dedt:ident
     ) => {
         impl SerialExt<BasicConfig> for $USARTX {
             fn usart(
@@ -464,10 +498,15 @@ macro_rules! uart_basic {
                 // Enable clock for USART
                 $USARTX::enable(rcc);
 
+                // The LPUART has no oversampling
+                if config.oversampling_8 && $clk_mul != 1 {
+                    return Err(InvalidConfig);
+                }
+
                 let clk = rcc.clocks.apb_clk.raw() as u64;
                 let bdr = config.baudrate.0 as u64;
-                let div = ($clk_mul * clk) / bdr;
-                usart.brr().write(|w| unsafe { w.bits(div as u32) });
+                let div = brr(clk, bdr, $clk_mul, config.oversampling_8);
+                usart.brr().write(|w| unsafe { w.bits(div) });
                 // Reset other registers to disable advanced USART features
                 usart.cr2().reset();
                 usart.cr3().reset();
@@ -476,7 +515,7 @@ macro_rules! uart_basic {
                 usart.cr1().modify(|_, w| w.ue().clear_bit());
 
                 // Enable transmission and receiving
-                usart.cr1().write(|w| {
+                usart.cr1().write(|w| unsafe {
                     w.te()
                         .set_bit()
                         .re()
@@ -489,7 +528,16 @@ macro_rules! uart_basic {
                         .bit(config.parity != Parity::ParityNone)
                         .ps()
                         .bit(config.parity == Parity::ParityOdd)
+                        .deat()
+                        .bits(config.driver_enable.assertion_time)
+                        .$dedt()
+                        .bits(config.driver_enable.deassertion_time)
                 });
+                if config.oversampling_8 {
+                    usart
+                        .cr1()
+                        .modify(|r, w| unsafe { w.bits(r.bits() | CR1_OVER8) });
+                }
 
                 usart.cr2().write(|w| unsafe {
                     w.stop()
@@ -507,7 +555,14 @@ macro_rules! uart_basic {
                         .bit(config.swap)
                 });
 
-                usart.cr3().write(|w| w.dem().bit(PINS::DRIVER_ENABLE));
+                usart.cr3().write(|w| {
+                    w.dem()
+                        .bit(PINS::DRIVER_ENABLE)
+                        .dep()
+                        .bit(config.driver_enable.active_low)
+                        .ovrdis()
+                        .bit(config.overrun_disable)
+                });
 
                 // Enable pins
                 pins.setup();
@@ -575,20 +630,6 @@ macro_rules! uart_basic {
                     .write(|w| unsafe { w.bits(event.val() & mask) });
             }
         }
-
-        impl fmt::Write for Tx<$USARTX, BasicConfig> {
-            fn write_str(&mut self, s: &str) -> fmt::Result {
-                let _ = s.as_bytes().iter().map(|c| block!(self.write(*c))).last();
-                Ok(())
-            }
-        }
-
-        impl fmt::Write for Serial<$USARTX, BasicConfig> {
-            fn write_str(&mut self, s: &str) -> fmt::Result {
-                let _ = s.as_bytes().iter().map(|c| block!(self.write(*c))).last();
-                Ok(())
-            }
-        }
     };
 }
 
@@ -619,9 +660,8 @@ macro_rules! uart_full {
 
                 let clk = rcc.clocks.apb_clk.raw() as u64;
                 let bdr = config.baudrate.0 as u64;
-                let clk_mul = 1;
-                let div = (clk_mul * clk) / bdr;
-                usart.brr().write(|w| unsafe { w.bits(div as u32) });
+                let div = brr(clk, bdr, $clk_mul, config.oversampling_8);
+                usart.brr().write(|w| unsafe { w.bits(div) });
 
                 usart.cr1().reset();
                 usart.cr2().reset();
@@ -640,6 +680,10 @@ macro_rules! uart_full {
                     usart.rtor().write(|w| unsafe { w.rto().bits(timeout) });
                 }
 
+                usart
+                    .cr1()
+                    .modify(|_, w| w.over8().bit(config.oversampling_8));
+
                 usart.cr3().write(|w| unsafe {
                     w.txftcfg()
                         .bits(config.tx_fifo_threshold.bits())
@@ -651,6 +695,10 @@ macro_rules! uart_full {
                         .bit(config.rx_fifo_interrupt)
                         .dem()
                         .bit(PINS::DRIVER_ENABLE)
+                        .dep()
+                        .bit(config.driver_enable.active_low)
+                        .ovrdis()
+                        .bit(config.overrun_disable)
                 });
 
                 usart.cr1().modify(|_, w| {
@@ -670,6 +718,10 @@ macro_rules! uart_full {
                         .bit(config.parity == Parity::ParityOdd)
                         .fifoen()
                         .bit(config.fifo_enable)
+                        .deat()
+                        .set(config.driver_enable.assertion_time)
+                        .dedt()
+                        .set(config.driver_enable.deassertion_time)
                 });
 
                 // Enable pins
@@ -764,20 +816,6 @@ macro_rules! uart_full {
                 usart.isr().read().rxft().bit_is_set()
             }
         }
-
-        impl fmt::Write for Tx<$USARTX, FullConfig> {
-            fn write_str(&mut self, s: &str) -> fmt::Result {
-                let _ = s.as_bytes().iter().map(|c| block!(self.write(*c))).last();
-                Ok(())
-            }
-        }
-
-        impl fmt::Write for Serial<$USARTX, FullConfig> {
-            fn write_str(&mut self, s: &str) -> fmt::Result {
-                let _ = s.as_bytes().iter().map(|c| block!(self.write(*c))).last();
-                Ok(())
-            }
-        }
     };
 }
 
@@ -889,17 +927,17 @@ uart_full!(USART2, usart2, 1);
     feature = "stm32g0b1",
     feature = "stm32g0c1",
 ))]
-uart_basic!(USART2, usart2, 1);
+uart_basic!(USART2, usart2, 1, dedt);
 
 #[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081",))]
-uart_basic!(USART3, usart3, 1);
+uart_basic!(USART3, usart3, 1, dedt);
 
 #[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081",))]
-uart_basic!(USART4, usart4, 1);
+uart_basic!(USART4, usart4, 1, dedt);
 
 // LPUART Should be given its own implementation when it needs to be used with features not present on
 // the basic feature set such as: Dual clock domain, FIFO or prescaler.
 // Or when Synchronous mode is implemented for the basic feature set, since the LP feature set does not have support.
 #[cfg(feature = "stm32g0x1")]
 #[cfg(not(any(feature = "stm32g0b1", feature = "stm32g0c1")))]
-uart_basic!(LPUART, lpuart, 256);
+uart_basic!(LPUART, lpuart, 256, dedt0);
@@ -0,0 +1,29 @@
+//! Device electronic signature
+//!
+//! Read-only identification values stored in system memory, see the
+//! reference manual section "Device electronic signature".
+use core::ptr;
+
+/// Base address of the 96 bit unique device ID
+const UID_BASE: usize = 0x1FFF_7590;
+/// Address of the flash size in kilobytes
+const FLASHSIZE_BASE: usize = 0x1FFF_75E0;
+
+/// Returns the 96 bit unique device ID, lowest word first
+pub fn device_id() -> [u32; 3] {
+    let uid = UID_BASE as *const u32;
+    // NOTE(unsafe) read-only system memory, always mapped
+    unsafe {
+        [
+            ptr::read_volatile(uid),
+            ptr::read_volatile(uid.add(1)),
+            ptr::read_volatile(uid.add(2)),
+        ]
+    }
+}
+
+/// Returns the size of the flash memory in kilobytes
+pub fn flash_size_kb() -> u16 {
+    // NOTE(unsafe) read-only system memory, always mapped
+    unsafe { ptr::read_volatile(FLASHSIZE_BASE as *const u16) }
+}
@@ -1,3 +1,4 @@
+use crate::dmamux::DmaMuxIndex;
 use crate::gpio::*;
 use crate::rcc::{self, Rcc};
 use crate::stm32::{self as pac, spi1};
@@ -9,7 +10,12 @@ use hal::digital::OutputPin;
 pub use hal::spi::{
     self, ErrorKind, ErrorType, Mode, Phase, Polarity, MODE_0, MODE_1, MODE_2, MODE_3,
 };
-use nb::block;
+
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod bitbang;
+
+pub use bitbang::BitBangSpi;
 
 /// SPI error
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -23,6 +29,8 @@ pub enum Error {
     Crc,
     /// Chip Select Fault
     ChipSelectFault,
+    /// DMA transfer error
+    Dma,
 }
 
 impl hal::spi::Error for Error {
@@ -31,20 +39,69 @@ impl hal::spi::Error for Error {
             Error::Overrun => ErrorKind::Overrun,
             Error::ModeFault => ErrorKind::ModeFault,
             Error::ChipSelectFault => ErrorKind::ChipSelectFault,
-            Error::Crc => ErrorKind::Other,
+            Error::Crc | Error::Dma => ErrorKind::Other,
+        }
+    }
+}
+
+/// SPI interrupt event
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpiEvent {
+    /// TX FIFO has room for a frame
+    Txe,
+    /// RX FIFO holds a frame
+    Rxne,
+    /// Overrun, mode fault or CRC error
+    Error,
+}
+
+/// Builds and inspects a [`Mode`] from its CPOL/CPHA bits, e.g. from a
+/// runtime configuration value
+pub trait ModeExt {
+    /// Returns the mode with clock polarity `cpol` (idle high when set) and
+    /// clock phase `cpha` (capture on second transition when set)
+    fn from_cpol_cpha(cpol: bool, cpha: bool) -> Self;
+    /// Returns the CPOL bit
+    fn cpol(&self) -> bool;
+    /// Returns the CPHA bit
+    fn cpha(&self) -> bool;
+}
+
+impl ModeExt for Mode {
+    fn from_cpol_cpha(cpol: bool, cpha: bool) -> Self {
+        match (cpol, cpha) {
+            (false, false) => MODE_0,
+            (false, true) => MODE_1,
+            (true, false) => MODE_2,
+            (true, true) => MODE_3,
         }
     }
+
+    fn cpol(&self) -> bool {
+        self.polarity == Polarity::IdleHigh
+    }
+
+    fn cpha(&self) -> bool {
+        self.phase == Phase::CaptureOnSecondTransition
+    }
 }
 
 pub trait Instance:
     crate::Sealed + core::ops::Deref<Target = spi1::RegisterBlock> + rcc::Enable + rcc::Reset
 {
+    /// DMAMUX request of the receive data register
+    fn dmamux_rx() -> DmaMuxIndex;
+
+    /// DMAMUX request of the transmit data register
+    fn dmamux_tx() -> DmaMuxIndex;
 }
 
 /// A filler type for when the delay is unnecessary
 pub struct NoDelay;
 
 impl DelayNs for NoDelay {
+    #[inline(always)]
     fn delay_ns(&mut self, _: u32) {}
 }
 
@@ -65,8 +122,97 @@ impl digital::OutputPin for NoCS {
     }
 }
 
+/// Chip select control used by `SpiDevice`
+///
+/// Implemented for every `OutputPin` (active low), and by `DecodedCs` for
+/// slaves selected through an address decoder.
+pub trait ChipSelect {
+    /// Selects the slave before a transaction
+    fn select(&mut self) -> Result<(), Error>;
+    /// Deselects the slave after a transaction
+    fn deselect(&mut self) -> Result<(), Error>;
+}
+
+impl<P: OutputPin> ChipSelect for P {
+    fn select(&mut self) -> Result<(), Error> {
+        self.set_low().map_err(|_| Error::ChipSelectFault)
+    }
+
+    fn deselect(&mut self) -> Result<(), Error> {
+        self.set_high().map_err(|_| Error::ChipSelectFault)
+    }
+}
+
+/// Chip select through an address decoder such as a 74HC138: the address
+/// pins select one of `2^N` slaves and the active low enable pin gates the
+/// decoder outputs.
+pub struct DecodedCs<ADDR, EN, const N: usize> {
+    addr: [ADDR; N],
+    enable: EN,
+    index: u8,
+}
+
+impl<ADDR: OutputPin, EN: OutputPin, const N: usize> DecodedCs<ADDR, EN, N> {
+    pub fn new(addr: [ADDR; N], mut enable: EN) -> Self {
+        assert!(N <= 8);
+        let _ = enable.set_high();
+        DecodedCs {
+            addr,
+            enable,
+            index: 0,
+        }
+    }
+
+    /// Sets the slave index used by the following transactions
+    pub fn set_index(&mut self, index: u8) {
+        assert!((index as u16) < 1 << N);
+        self.index = index;
+    }
+
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    pub fn release(self) -> ([ADDR; N], EN) {
+        (self.addr, self.enable)
+    }
+}
+
+impl<ADDR: OutputPin, EN: OutputPin, const N: usize> ChipSelect for DecodedCs<ADDR, EN, N> {
+    fn select(&mut self) -> Result<(), Error> {
+        for (bit, pin) in self.addr.iter_mut().enumerate() {
+            let res = if self.index & (1 << bit) != 0 {
+                pin.set_high()
+            } else {
+                pin.set_low()
+            };
+            res.map_err(|_| Error::ChipSelectFault)?;
+        }
+        self.enable.set_low().map_err(|_| Error::ChipSelectFault)
+    }
+
+    fn deselect(&mut self) -> Result<(), Error> {
+        self.enable.set_high().map_err(|_| Error::ChipSelectFault)
+    }
+}
+
 /// A filler type for when the SCK pin is unnecessary
 pub struct NoSck;
+impl digital::ErrorType for NoSck {
+    type Error = Infallible;
+}
+
+/// Without an SCK pin `SpiBus::with_sck_as_gpio` drives nothing
+impl OutputPin for NoSck {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
 /// A filler type for when the Miso pin is unnecessary
 pub struct NoMiso;
 /// A filler type for when the Mosi pin is unnecessary
@@ -77,17 +223,33 @@ pub trait Pins<SPI> {
     fn release(self) -> Self;
 }
 
-pub trait PinSck<SPI> {
+impl crate::Sealed for NoSck {}
+impl crate::Sealed for NoMiso {}
+impl crate::Sealed for NoMosi {}
+
+/// SCK pin of `SPI`. Sealed: only the pins routed to `SPI` implement it, with
+/// the alternate function from the datasheet, so a wrong pin does not compile.
+pub trait PinSck<SPI>: crate::Sealed {
+    fn setup(&self);
+    fn release(self) -> Self;
+    /// Drives the pin as a push pull output while `f` runs
+    fn with_gpio<R>(&mut self, f: impl FnOnce(&mut dyn OutputPin<Error = Infallible>) -> R) -> R;
+}
+
+/// MISO pin of `SPI`, sealed like [`PinSck`]
+pub trait PinMiso<SPI>: crate::Sealed {
     fn setup(&self);
     fn release(self) -> Self;
 }
 
-pub trait PinMiso<SPI> {
+/// MOSI pin of `SPI`, sealed like [`PinSck`]
+pub trait PinMosi<SPI>: crate::Sealed {
     fn setup(&self);
     fn release(self) -> Self;
 }
 
-pub trait PinMosi<SPI> {
+/// NSS pin of `SPI`, sealed like [`PinSck`]
+pub trait PinNss<SPI>: crate::Sealed {
     fn setup(&self);
     fn release(self) -> Self;
 }
@@ -109,10 +271,61 @@ where
     }
 }
 
+impl<SPI, PINS, NSS> Pins<SPI> for (PINS, NSS)
+where
+    PINS: Pins<SPI>,
+    NSS: PinNss<SPI>,
+{
+    fn setup(&self) {
+        self.0.setup();
+        self.1.setup();
+    }
+
+    fn release(self) -> Self {
+        (self.0.release(), self.1.release())
+    }
+}
+
+/// SPI master bus
+///
+/// Dropping the bus disables the peripheral, but leaves the pins in their
+/// alternate function; use [`SpiBus::release`] to get the pins back.
 #[derive(Debug)]
-pub struct SpiBus<SPI, PINS> {
+pub struct SpiBus<SPI: Instance, PINS> {
     spi: SPI,
     pins: PINS,
+    clk: Hertz,
+    tx_only: bool,
+    #[cfg(feature = "spi-trace")]
+    trace: Option<SpiTrace>,
+}
+
+/// A byte recorded by the SPI trace
+#[cfg(feature = "spi-trace")]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceEntry {
+    /// Byte written to MOSI
+    Sent(u8),
+    /// Byte read from MISO
+    Received(u8),
+}
+
+#[cfg(feature = "spi-trace")]
+#[derive(Debug)]
+struct SpiTrace {
+    buffer: &'static mut [TraceEntry],
+    len: usize,
+}
+
+#[cfg(feature = "spi-trace")]
+impl SpiTrace {
+    fn record(&mut self, entry: TraceEntry) {
+        if let Some(slot) = self.buffer.get_mut(self.len) {
+            *slot = entry;
+            self.len += 1;
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -120,21 +333,71 @@ pub struct SpiDevice<BUS, CS, DELAY> {
     bus: BUS,
     cs: CS,
     delay: DELAY,
+    cs_setup_ns: u32,
+    cs_hold_ns: u32,
 }
 
-pub trait SpiExt: Sized {
+/// Returns the BR prescaler setting nearest to `target` and the resulting
+/// SCK frequency, `apb_clk / 2^(br + 1)`. A `target` above `apb_clk / 2`
+/// selects the fastest prescaler, a `target` of 0 the slowest one.
+pub fn spi_prescaler(apb_clk: Hertz, target: Hertz) -> (u8, Hertz) {
+    let br = match apb_clk.raw().checked_div(target.raw()) {
+        None => 0b111,
+        Some(0..=2) => 0b000,
+        Some(3..=5) => 0b001,
+        Some(6..=11) => 0b010,
+        Some(12..=23) => 0b011,
+        Some(24..=47) => 0b100,
+        Some(48..=95) => 0b101,
+        Some(96..=191) => 0b110,
+        Some(_) => 0b111,
+    };
+    (br, apb_clk / (2 << br))
+}
+
+pub trait SpiExt: Sized + Instance {
     fn spi<PINS>(self, pins: PINS, mode: Mode, freq: Hertz, rcc: &mut Rcc) -> SpiBus<Self, PINS>
     where
         PINS: Pins<Self>;
 }
 
 macro_rules! spi {
-    ($SPIX:ty,
+    ($SPIX:ty, $DMAMUX_RX:ident, $DMAMUX_TX:ident,
         sck: [ $(($SCK:ty, $SCK_AF:expr),)+ ],
         miso: [ $(($MISO:ty, $MISO_AF:expr),)+ ],
         mosi: [ $(($MOSI:ty, $MOSI_AF:expr),)+ ],
+        nss: [ $(($NSS:ty, $NSS_AF:expr),)+ ],
     ) => {
-        impl Instance for $SPIX {}
+        impl Instance for $SPIX {
+            fn dmamux_rx() -> DmaMuxIndex {
+                DmaMuxIndex::$DMAMUX_RX
+            }
+
+            fn dmamux_tx() -> DmaMuxIndex {
+                DmaMuxIndex::$DMAMUX_TX
+            }
+        }
+
+        impl<PINS: Pins<$SPIX>> SpiBus<$SPIX, PINS> {
+            /// Wraps the already configured peripheral again, e.g. in a panic
+            /// handler or after a bootloader handoff, without resetting it
+            ///
+            /// # Safety
+            ///
+            /// Advanced use only: no other owner of the peripheral may access
+            /// it while the returned bus is in use, and `pins` must already be
+            /// set up for SPI.
+            pub unsafe fn steal(pins: PINS, rcc: &Rcc) -> Self {
+                SpiBus {
+                    spi: <$SPIX>::steal(),
+                    pins,
+                    clk: rcc.clocks.apb_clk,
+                    tx_only: false,
+                    #[cfg(feature = "spi-trace")]
+                    trace: None,
+                }
+            }
+        }
 
         impl PinSck<$SPIX> for NoSck {
             fn setup(&self) {}
@@ -142,6 +405,10 @@ macro_rules! spi {
             fn release(self) -> Self {
                 self
             }
+
+            fn with_gpio<R>(&mut self, f: impl FnOnce(&mut dyn OutputPin<Error = Infallible>) -> R) -> R {
+                f(self)
+            }
         }
 
         impl PinMiso<$SPIX> for NoMiso {
@@ -169,6 +436,10 @@ macro_rules! spi {
                 fn release(self) -> Self {
                     self.into_analog()
                 }
+
+                fn with_gpio<R>(&mut self, f: impl FnOnce(&mut dyn OutputPin<Error = Infallible>) -> R) -> R {
+                    self.with_push_pull_output(|pin| f(pin))
+                }
             }
         )*
         $(
@@ -188,6 +459,17 @@ macro_rules! spi {
                     self.set_alt_mode($MOSI_AF);
                 }
 
+                fn release(self) -> Self {
+                    self.into_analog()
+                }
+            }
+        )*
+        $(
+            impl PinNss<$SPIX> for $NSS {
+                fn setup(&self) {
+                    self.set_alt_mode($NSS_AF);
+                }
+
                 fn release(self) -> Self {
                     self.into_analog()
                 }
@@ -197,6 +479,8 @@ macro_rules! spi {
 }
 
 impl<SPI: Instance, PINS: Pins<SPI>> SpiBus<SPI, PINS> {
+    /// Creates the bus with the prescaler nearest to `speed`, see
+    /// [`spi_prescaler`]; `frequency` returns the actual SCK frequency.
     pub fn new(spi: SPI, pins: PINS, mode: Mode, speed: Hertz, rcc: &mut Rcc) -> Self {
         SPI::enable(rcc);
         SPI::reset(rcc);
@@ -204,17 +488,7 @@ impl<SPI: Instance, PINS: Pins<SPI>> SpiBus<SPI, PINS> {
         // disable SS output
         spi.cr2().write(|w| w.ssoe().clear_bit());
 
-        let br = match rcc.clocks.apb_clk / speed {
-            0 => unreachable!(),
-            1..=2 => 0b000,
-            3..=5 => 0b001,
-            6..=11 => 0b010,
-            12..=23 => 0b011,
-            24..=47 => 0b100,
-            48..=95 => 0b101,
-            96..=191 => 0b110,
-            _ => 0b111,
-        };
+        let (br, _) = spi_prescaler(rcc.clocks.apb_clk, speed);
 
         spi.cr2()
             .write(|w| unsafe { w.frxth().set_bit().ds().bits(0b111).ssoe().clear_bit() });
@@ -223,8 +497,8 @@ impl<SPI: Instance, PINS: Pins<SPI>> SpiBus<SPI, PINS> {
         pins.setup();
 
         spi.cr1().write(|w| {
-            w.cpha().bit(mode.phase == Phase::CaptureOnSecondTransition);
-            w.cpol().bit(mode.polarity == Polarity::IdleHigh);
+            w.cpha().bit(mode.cpha());
+            w.cpol().bit(mode.cpol());
             w.mstr().set_bit();
             w.br().set(br);
             w.lsbfirst().clear_bit();
@@ -236,10 +510,17 @@ impl<SPI: Instance, PINS: Pins<SPI>> SpiBus<SPI, PINS> {
             w.spe().set_bit()
         });
 
-        SpiBus { spi, pins }
+        SpiBus {
+            spi,
+            pins,
+            clk: rcc.clocks.apb_clk,
+            tx_only: false,
+            #[cfg(feature = "spi-trace")]
+            trace: None,
+        }
     }
 
-    pub fn exclusive<CS: OutputPin, DELAY: DelayNs>(
+    pub fn exclusive<CS: ChipSelect, DELAY: DelayNs>(
         self,
         cs: CS,
         delay: DELAY,
@@ -248,15 +529,329 @@ impl<SPI: Instance, PINS: Pins<SPI>> SpiBus<SPI, PINS> {
             bus: self,
             cs,
             delay,
+            cs_setup_ns: 0,
+            cs_hold_ns: 0,
         }
     }
 
+    /// Like `exclusive`, without a delay provider: `Operation::DelayNs` and
+    /// the chip select delays are no-ops
+    pub fn exclusive_no_delay<CS: ChipSelect>(
+        self,
+        cs: CS,
+    ) -> SpiDevice<SpiBus<SPI, PINS>, CS, NoDelay> {
+        self.exclusive(cs, NoDelay)
+    }
+
+    /// Returns the actual SCK frequency, `apb_clk / 2^(br + 1)`
+    pub fn frequency(&self) -> Hertz {
+        let br = self.spi.cr1().read().br().bits() as u32;
+        self.clk / (2 << br)
+    }
+
+    /// Sets the frame size to `nr_bits` (4 to 8).
+    ///
+    /// The bus transfers `u8` words with byte-wide data register access, so
+    /// frames wider than 8 bits are not supported.
     pub fn data_size(&mut self, nr_bits: u8) {
+        assert!(
+            (4..=8).contains(&nr_bits),
+            "SPI frame size must be 4 to 8 bits with u8 words"
+        );
         self.spi
             .cr2()
             .modify(|_, w| unsafe { w.ds().bits(nr_bits - 1) });
     }
 
+    /// Lets the peripheral drive the NSS pin as chip select (SSOE set, SSM cleared).
+    ///
+    /// NSS is driven low as soon as the SPI is enabled and stays low until it
+    /// is disabled, so this only suits a single slave on the bus. With several
+    /// slaves keep the software NSS default and use a GPIO chip select per
+    /// device through `exclusive`.
+    ///
+    /// The pending frames are sent before the switch. The returned bus keeps
+    /// `pin` with its other pins, `release` hands it back.
+    pub fn hardware_nss<NSS: PinNss<SPI>>(mut self, pin: NSS) -> SpiBus<SPI, (PINS, NSS)> {
+        self.disable_peripheral();
+        pin.setup();
+        self.spi.cr2().modify(|_, w| w.ssoe().set_bit());
+        self.spi
+            .cr1()
+            .modify(|_, w| w.ssm().clear_bit().ssi().clear_bit().spe().set_bit());
+
+        let bus = core::mem::ManuallyDrop::new(self);
+        // NOTE(unsafe) the fields are moved out of a bus that is never
+        // dropped, so each is read exactly once
+        unsafe {
+            SpiBus {
+                spi: core::ptr::read(&bus.spi),
+                pins: (core::ptr::read(&bus.pins), pin),
+                clk: bus.clk,
+                tx_only: bus.tx_only,
+                #[cfg(feature = "spi-trace")]
+                trace: core::ptr::read(&bus.trace),
+            }
+        }
+    }
+
+    /// Sets the internal NSS level used while software NSS is selected. A
+    /// master with the level low runs into a mode fault.
+    pub fn set_internal_nss(&mut self, high: bool) {
+        self.spi.cr1().modify(|_, w| w.ssi().bit(high));
+    }
+
+    /// Enables software NSS (SSM): the level set by `set_internal_nss`
+    /// replaces the NSS pin input. When disabled the NSS pin is used.
+    pub fn set_software_nss(&mut self, enable: bool) {
+        self.spi.cr1().modify(|_, w| w.ssm().bit(enable));
+    }
+
+    /// Disables the peripheral, keeping its configuration.
+    ///
+    /// The last frame is completed first: the TX FIFO is drained, BSY is
+    /// awaited and the RX FIFO emptied around clearing SPE.
+    pub fn disable(&mut self) {
+        self.disable_peripheral();
+    }
+
+    /// Enables the peripheral again after `disable`
+    pub fn enable(&mut self) {
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+    }
+
+    /// Switches the bus to slave mode with software NSS held selected.
+    ///
+    /// The ongoing transfer is completed and the peripheral disabled before
+    /// MSTR is cleared, then the SPI is enabled again.
+    pub fn into_slave(mut self) -> Self {
+        self.disable_peripheral();
+        self.spi
+            .cr1()
+            .modify(|_, w| w.mstr().clear_bit().ssm().set_bit().ssi().clear_bit());
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+        self
+    }
+
+    /// Switches the bus back to master mode with software NSS
+    pub fn into_master(mut self) -> Self {
+        self.disable_peripheral();
+        self.spi.cr2().modify(|_, w| w.ssoe().clear_bit());
+        self.spi
+            .cr1()
+            .modify(|_, w| w.mstr().set_bit().ssm().set_bit().ssi().set_bit());
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+        self
+    }
+
+    /// Selects the transmit-only write path.
+    ///
+    /// When enabled, `write` only waits on TXE for each frame and BSY at the
+    /// end, instead of reading back every received frame. Received data and
+    /// the resulting overrun are discarded once the write completes, so use
+    /// this only when MISO is not connected (e.g. with `NoMiso`).
+    pub fn tx_only(&mut self, enable: bool) {
+        self.tx_only = enable;
+    }
+
+    /// Starts listening for an interrupt event
+    pub fn listen(&mut self, event: SpiEvent) {
+        self.spi.cr2().modify(|_, w| match event {
+            SpiEvent::Txe => w.txeie().set_bit(),
+            SpiEvent::Rxne => w.rxneie().set_bit(),
+            SpiEvent::Error => w.errie().set_bit(),
+        });
+    }
+
+    /// Stops listening for an interrupt event
+    pub fn unlisten(&mut self, event: SpiEvent) {
+        self.spi.cr2().modify(|_, w| match event {
+            SpiEvent::Txe => w.txeie().clear_bit(),
+            SpiEvent::Rxne => w.rxneie().clear_bit(),
+            SpiEvent::Error => w.errie().clear_bit(),
+        });
+    }
+
+    /// Checks if an interrupt event is pending
+    pub fn is_pending(&self, event: SpiEvent) -> bool {
+        let sr = self.spi.sr().read();
+        match event {
+            SpiEvent::Txe => sr.txe().bit_is_set(),
+            SpiEvent::Rxne => sr.rxne().bit_is_set(),
+            SpiEvent::Error => {
+                sr.ovr().bit_is_set() || sr.modf().bit_is_set() || sr.crcerr().bit_is_set()
+            }
+        }
+    }
+
+    /// Returns true while a frame is being shifted or the TX FIFO is not empty
+    pub fn is_busy(&self) -> bool {
+        self.spi.sr().read().bsy().bit_is_set()
+    }
+
+    /// Returns true if a received frame can be read
+    pub fn is_rx_not_empty(&self) -> bool {
+        self.spi.sr().read().rxne().bit_is_set()
+    }
+
+    /// Returns true if the TX FIFO has room for a frame
+    pub fn is_tx_empty(&self) -> bool {
+        self.spi.sr().read().txe().bit_is_set()
+    }
+
+    /// Clears the overrun flag, discarding the pending received frame
+    pub fn clear_overrun(&mut self) {
+        let _ = self.spi.dr8().read().bits();
+        let _ = self.spi.sr().read();
+    }
+
+    /// Clears the CRC error flag
+    pub fn clear_crc_error(&mut self) {
+        self.spi.sr().modify(|_, w| w.crcerr().clear_bit());
+    }
+
+    /// Enables hardware CRC calculation with an 8 bit CRC and `polynomial`
+    pub fn enable_crc(&mut self, polynomial: u8) {
+        self.disable_peripheral();
+        self.spi
+            .crcpr()
+            .write(|w| w.crcpoly().set(polynomial as u16));
+        self.spi
+            .cr1()
+            .modify(|_, w| w.crcl().clear_bit().crcen().set_bit());
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+    }
+
+    /// Disables hardware CRC calculation
+    pub fn disable_crc(&mut self) {
+        self.disable_peripheral();
+        self.spi.cr1().modify(|_, w| w.crcen().clear_bit());
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+    }
+
+    /// Transfers `write` followed by the CRC while reading into `read`, and
+    /// repeats the whole transfer up to `max_retries` times when the received
+    /// CRC does not match. Requires `enable_crc`. Returns the last error if
+    /// all attempts fail.
+    pub fn transfer_with_crc_retry(
+        &mut self,
+        write: &[u8],
+        read: &mut [u8],
+        max_retries: u8,
+    ) -> Result<(), Error> {
+        let mut attempt = 0;
+        loop {
+            match self.transfer_crc(read, write) {
+                Err(Error::Crc) if attempt < max_retries => {
+                    attempt += 1;
+                    // flush the FIFOs and restart the CRC calculation
+                    self.wait_idle();
+                    self.clear_crc_error();
+                    self.disable_peripheral();
+                    self.spi.cr1().modify(|_, w| w.crcen().clear_bit());
+                    self.spi.cr1().modify(|_, w| w.crcen().set_bit());
+                    self.spi.cr1().modify(|_, w| w.spe().set_bit());
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Records every byte sent and received into `buffer`. Recording stops
+    /// once the buffer is full.
+    #[cfg(feature = "spi-trace")]
+    pub fn with_trace(mut self, buffer: &'static mut [TraceEntry]) -> Self {
+        self.trace = Some(SpiTrace { buffer, len: 0 });
+        self
+    }
+
+    /// Returns the bytes recorded so far
+    #[cfg(feature = "spi-trace")]
+    pub fn trace(&self) -> &[TraceEntry] {
+        match &self.trace {
+            Some(trace) => &trace.buffer[..trace.len],
+            None => &[],
+        }
+    }
+
+    /// Discards the recorded bytes and restarts recording
+    #[cfg(feature = "spi-trace")]
+    pub fn clear_trace(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            trace.len = 0;
+        }
+    }
+
+    /// Recovers from a mode fault, which clears MSTR and SPE in hardware.
+    ///
+    /// MODF is cleared by a SR read followed by a CR1 write, after which
+    /// master mode and the peripheral are enabled again. Returns false if no
+    /// mode fault was pending.
+    pub fn recover_mode_fault(&mut self) -> bool {
+        if self.spi.sr().read().modf().bit_is_clear() {
+            return false;
+        }
+        self.spi.cr1().modify(|_, w| w.mstr().set_bit());
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+        true
+    }
+
+    /// Feeds the TX FIFO from `next` while it has room, for use in the SPI
+    /// interrupt handler with `SpiEvent::Txe` listened.
+    ///
+    /// When `next` runs out of data the TXE interrupt is disabled and `true`
+    /// is returned. Received frames are not read, so the RX FIFO overruns
+    /// during the stream; call `clear_overrun` once the bus is idle.
+    pub fn service_tx<F: FnMut() -> Option<u8>>(&mut self, mut next: F) -> bool {
+        while self.spi.sr().read().txe().bit_is_set() {
+            match next() {
+                Some(byte) => {
+                    self.spi.dr8().write(|w| unsafe { w.dr().bits(byte as _) });
+                }
+                None => {
+                    self.unlisten(SpiEvent::Txe);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Runs a loopback self test and returns `true` if it passed.
+    ///
+    /// The G0 SPI has no internal loopback, so MOSI must be jumpered to MISO
+    /// for the test. A fixed pattern is transferred and compared with the
+    /// received data.
+    pub fn self_test(&mut self) -> Result<bool, Error> {
+        use crate::hal::spi::SpiBus;
+
+        const PATTERN: [u8; 6] = [0x55, 0xaa, 0x00, 0xff, 0x5a, 0xa5];
+        let mut read = [0u8; PATTERN.len()];
+        self.transfer(&mut read, &PATTERN)?;
+        Ok(read == PATTERN)
+    }
+
+    /// Like `transfer`, but frames past the end of `write` are sent as
+    /// `dummy` instead of 0, e.g. the 0xff SD cards expect while reading
+    pub fn transfer_with_dummy(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+        dummy: u8,
+    ) -> Result<(), Error> {
+        let len = read.len().max(write.len());
+        self.exchange(read, Some(write), len, dummy, 0)
+    }
+
+    /// Writes `write`, then clocks in `read.len()` frames while sending 0,
+    /// e.g. a flash read command followed by its response. Both phases run
+    /// as one exchange that keeps the FIFO filled, so there is no gap between
+    /// command and response.
+    pub fn transfer_then_read(&mut self, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        let len = write.len() + read.len();
+        self.exchange(read, Some(write), len, 0, write.len())
+    }
+
     pub fn half_duplex_enable(&mut self, enable: bool) {
         self.spi.cr1().modify(|_, w| w.bidimode().bit(enable));
     }
@@ -265,41 +860,226 @@ impl<SPI: Instance, PINS: Pins<SPI>> SpiBus<SPI, PINS> {
         self.spi.cr1().modify(|_, w| w.bidioe().bit(enable));
     }
 
+    /// Receives exactly `buf.len()` frames in half-duplex mode.
+    ///
+    /// In bidirectional receive mode the master clocks continuously while
+    /// SPE is set, so SPE is cleared while the last frame is ongoing, then
+    /// the remaining frames are taken from the RX FIFO once the bus is idle.
+    /// Interrupts are disabled during the transfer to hit that window; use a
+    /// baud rate prescaler of 8 or more so the loop keeps up with the bus.
+    /// The bus is left in half-duplex transmit mode.
+    pub fn half_duplex_read(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        self.disable_peripheral();
+        self.spi
+            .cr1()
+            .modify(|_, w| w.bidimode().set_bit().bidioe().clear_bit());
+
+        let mut received = 0;
+        cortex_m::interrupt::free(|_| {
+            self.spi.cr1().modify(|_, w| w.spe().set_bit());
+            while received + 1 < buf.len() {
+                if self.spi.sr().read().rxne().bit_is_set() {
+                    buf[received] = self.spi.dr8().read().bits();
+                    received += 1;
+                }
+            }
+            self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+        });
+
+        while self.spi.sr().read().bsy().bit_is_set() {}
+        let overrun = self.spi.sr().read().ovr().bit_is_set();
+        while self.spi.sr().read().frlvl().bits() != 0 {
+            let byte = self.spi.dr8().read().bits();
+            if let Some(slot) = buf.get_mut(received) {
+                *slot = byte;
+                received += 1;
+            }
+        }
+        let _ = self.spi.sr().read();
+
+        self.spi.cr1().modify(|_, w| w.bidioe().set_bit());
+        self.spi.cr1().modify(|_, w| w.spe().set_bit());
+        if overrun {
+            Err(Error::Overrun)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes `cmd` and reads `buf` in 3-wire mode, where MOSI is the only
+    /// data line and MISO can be `NoMiso`. The data line is released for
+    /// about one SCK period between both phases. The bus is left in
+    /// half-duplex transmit mode.
+    pub fn write_then_read_3wire(&mut self, cmd: &[u8], buf: &mut [u8]) -> Result<(), Error> {
+        self.disable_peripheral();
+        self.spi
+            .cr1()
+            .modify(|_, w| w.bidimode().set_bit().bidioe().set_bit().spe().set_bit());
+        self.write_tx_only(cmd)?;
+        cortex_m::asm::delay(self.clk.raw() / self.frequency().raw());
+        self.half_duplex_read(buf)
+    }
+
+    /// Releases the peripheral and the pins, leaving the peripheral as is
     pub fn release(self) -> (SPI, PINS) {
-        (self.spi, self.pins.release())
+        let bus = core::mem::ManuallyDrop::new(self);
+        // NOTE(unsafe) the fields are moved out of a bus that is never
+        // dropped, so each is read exactly once
+        let (spi, pins) = unsafe { (core::ptr::read(&bus.spi), core::ptr::read(&bus.pins)) };
+        (spi, pins.release())
+    }
+}
+
+impl<SPI: Instance, PINS> Drop for SpiBus<SPI, PINS> {
+    /// Disables the peripheral, after the pending frames went out when it is
+    /// the bus master
+    fn drop(&mut self) {
+        if self.spi.cr1().read().mstr().bit_is_set() {
+            self.disable_peripheral();
+        } else {
+            self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+        }
+    }
+}
+
+impl<SPI: Instance, SCK: PinSck<SPI>, MISO: PinMiso<SPI>, MOSI: PinMosi<SPI>>
+    SpiBus<SPI, (SCK, MISO, MOSI)>
+{
+    /// Drives SCK as a push pull GPIO output while `f` runs, e.g. to clock a
+    /// device manually during its reset sequence, then gives it back to the
+    /// SPI. The bus must be idle.
+    pub fn with_sck_as_gpio<R>(
+        &mut self,
+        f: impl FnOnce(&mut dyn OutputPin<Error = Infallible>) -> R,
+    ) -> R {
+        self.pins.0.with_gpio(f)
+    }
+}
+
+impl<BUS, CS: ChipSelect, DELAY: DelayNs> SpiDevice<BUS, CS, DELAY> {
+    /// Sets the delays inserted after selecting the slave (setup) and
+    /// before deselecting it (hold) in every transaction
+    pub fn with_cs_delays(mut self, setup_ns: u32, hold_ns: u32) -> Self {
+        self.cs_setup_ns = setup_ns;
+        self.cs_hold_ns = hold_ns;
+        self
+    }
+
+    /// Gives access to the chip select, e.g. to change the `DecodedCs` index
+    pub fn chip_select(&mut self) -> &mut CS {
+        &mut self.cs
+    }
+}
+
+impl<SPI: Instance, PINS, CS: ChipSelect, DELAY: DelayNs> SpiDevice<SpiBus<SPI, PINS>, CS, DELAY> {
+    /// Writes `write`, then reads `read` within a single chip select
+    /// assertion, e.g. a flash command and address followed by the data
+    pub fn write_read(&mut self, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        use crate::hal::spi::SpiDevice;
+        self.transaction(&mut [spi::Operation::Write(write), spi::Operation::Read(read)])
+    }
+}
+
+impl<SPI: Instance, PINS, CS: ChipSelect, DELAY: DelayNs> SpiDevice<SpiBus<SPI, PINS>, CS, DELAY> {
+    /// Selects the slave and returns a guard for several bus operations
+    /// under one chip select assertion, e.g. when later bytes depend on
+    /// earlier reads. The slave is deselected when the guard is dropped.
+    pub fn start_transaction(&mut self) -> Result<SpiTransaction<'_, SPI, PINS, CS, DELAY>, Error> {
+        self.bus.clear_rx_fifo();
+        self.cs.select()?;
+        if self.cs_setup_ns > 0 {
+            self.delay.delay_ns(self.cs_setup_ns);
+        }
+        Ok(SpiTransaction {
+            device: self,
+            active: true,
+        })
+    }
+}
+
+/// Chip select assertion started with [`SpiDevice::start_transaction`]
+pub struct SpiTransaction<'a, SPI: Instance, PINS, CS: ChipSelect, DELAY: DelayNs> {
+    device: &'a mut SpiDevice<SpiBus<SPI, PINS>, CS, DELAY>,
+    active: bool,
+}
+
+impl<SPI: Instance, PINS, CS: ChipSelect, DELAY: DelayNs> SpiTransaction<'_, SPI, PINS, CS, DELAY> {
+    pub fn read(&mut self, read: &mut [u8]) -> Result<(), Error> {
+        spi::SpiBus::read(&mut self.device.bus, read)
+    }
+
+    pub fn write(&mut self, write: &[u8]) -> Result<(), Error> {
+        spi::SpiBus::write(&mut self.device.bus, write)
+    }
+
+    pub fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        spi::SpiBus::transfer(&mut self.device.bus, read, write)
+    }
+
+    pub fn transfer_in_place(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        spi::SpiBus::transfer_in_place(&mut self.device.bus, data)
+    }
+
+    /// Deselects the slave after the hold delay, returning a chip select
+    /// error that dropping the guard would ignore
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.end()
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        if !self.active {
+            return Ok(());
+        }
+        self.active = false;
+        if self.device.cs_hold_ns > 0 {
+            self.device.delay.delay_ns(self.device.cs_hold_ns);
+        }
+        self.device.cs.deselect()
+    }
+}
+
+impl<SPI: Instance, PINS, CS: ChipSelect, DELAY: DelayNs> Drop
+    for SpiTransaction<'_, SPI, PINS, CS, DELAY>
+{
+    fn drop(&mut self) {
+        let _ = self.end();
     }
 }
 
-impl<SPI: Instance, PINS, CS: OutputPin, DELAY> ErrorType
+impl<SPI: Instance, PINS, CS: ChipSelect, DELAY> ErrorType
     for SpiDevice<SpiBus<SPI, PINS>, CS, DELAY>
 {
     type Error = Error;
 }
-impl<SPI: Instance, PINS, CS: OutputPin, DELAY: DelayNs> spi::SpiDevice
+impl<SPI: Instance, PINS, CS: ChipSelect, DELAY: DelayNs> spi::SpiDevice
     for SpiDevice<SpiBus<SPI, PINS>, CS, DELAY>
 {
     fn transaction(&mut self, operations: &mut [hal::spi::Operation<'_, u8>]) -> Result<(), Error> {
         use crate::hal::spi::SpiBus;
-        self.cs.set_low().map_err(|_| Error::ChipSelectFault)?;
-        for op in operations {
-            match op {
-                spi::Operation::Read(read) => {
-                    self.bus.read(read)?;
-                }
-                spi::Operation::Write(write) => {
-                    self.bus.write(write)?;
-                }
-                spi::Operation::Transfer(write, read) => {
-                    self.bus.transfer(write, read)?;
-                }
-                spi::Operation::TransferInPlace(data) => {
-                    self.bus.transfer_in_place(data)?;
-                }
-                spi::Operation::DelayNs(ns) => self.delay.delay_ns(*ns),
+        self.bus.clear_rx_fifo();
+        self.cs.select()?;
+        if self.cs_setup_ns > 0 {
+            self.delay.delay_ns(self.cs_setup_ns);
+        }
+        // run the operations first so CS is released even if one fails
+        let result = operations.iter_mut().try_for_each(|op| match op {
+            spi::Operation::Read(read) => self.bus.read(read),
+            spi::Operation::Write(write) => self.bus.write(write),
+            spi::Operation::Transfer(write, read) => self.bus.transfer(write, read),
+            spi::Operation::TransferInPlace(data) => self.bus.transfer_in_place(data),
+            spi::Operation::DelayNs(ns) => {
+                self.delay.delay_ns(*ns);
+                Ok(())
             }
+        });
+        if result.is_ok() && self.cs_hold_ns > 0 {
+            self.delay.delay_ns(self.cs_hold_ns);
         }
-        self.cs.set_high().map_err(|_| Error::ChipSelectFault)?;
-        Ok(())
+        let deselect = self.cs.deselect();
+        result.and(deselect)
     }
 }
 
@@ -313,6 +1093,145 @@ impl<SPI: Instance> SpiExt for SPI {
 }
 
 impl<SPI: Instance, PINS> SpiBus<SPI, PINS> {
+    /// Discards stale frames left in the RX FIFO, e.g. by an aborted
+    /// transfer, and clears a pending overrun. `SpiDevice` does this at the
+    /// start of every transaction.
+    pub fn clear_rx_fifo(&mut self) {
+        while self.spi.sr().read().frlvl().bits() != 0 {
+            let _ = self.spi.dr8().read().bits();
+        }
+        let _ = self.spi.sr().read();
+    }
+
+    /// Disables the peripheral following the reference manual procedure:
+    /// wait until the TX FIFO is empty and the bus idle, clear SPE, then
+    /// drain the RX FIFO.
+    ///
+    /// In receive-only modes the master clocks continuously and BSY never
+    /// clears, so SPE is cleared first and BSY awaited for the last frame.
+    fn disable_peripheral(&mut self) {
+        let cr1 = self.spi.cr1().read();
+        let rx_only = cr1.rxonly().bit_is_set()
+            || (cr1.bidimode().bit_is_set() && cr1.bidioe().bit_is_clear());
+        if rx_only {
+            self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+            while self.spi.sr().read().bsy().bit_is_set() {}
+        } else {
+            while self.spi.sr().read().ftlvl().bits() != 0 {}
+            while self.spi.sr().read().bsy().bit_is_set() {}
+            self.spi.cr1().modify(|_, w| w.spe().clear_bit());
+        }
+        while self.spi.sr().read().frlvl().bits() != 0 {
+            let _ = self.spi.dr8().read().bits();
+        }
+    }
+
+    /// Writes frames without reading back the received data, then waits for
+    /// the bus to go idle and discards the RX FIFO and overrun flag.
+    fn write_tx_only(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        for byte in bytes.iter() {
+            loop {
+                let sr = self.spi.sr().read();
+                if sr.modf().bit_is_set() {
+                    return Err(Error::ModeFault);
+                }
+                if sr.txe().bit_is_set() {
+                    break;
+                }
+            }
+            self.spi.dr8().write(|w| unsafe { w.dr().bits(*byte as _) });
+            #[cfg(feature = "spi-trace")]
+            if let Some(trace) = &mut self.trace {
+                trace.record(TraceEntry::Sent(*byte));
+            }
+        }
+        self.wait_idle();
+        Ok(())
+    }
+
+    /// Transfers frames one at a time, then the CRC, and checks the received
+    /// CRC once the bus is idle
+    fn transfer_crc(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let len = read.len().max(write.len());
+        if len == 0 {
+            return Ok(());
+        }
+        for idx in 0..len {
+            nb::block!(self.send_byte(write.get(idx).copied().unwrap_or(0)))?;
+            if idx + 1 == len {
+                self.spi.cr1().modify(|_, w| w.crcnext().set_bit());
+            }
+            let byte = nb::block!(self.receive_byte())?;
+            if let Some(r) = read.get_mut(idx) {
+                *r = byte;
+            }
+        }
+        // the received CRC lands in the RX FIFO
+        while self.spi.sr().read().rxne().bit_is_clear() {}
+        let _ = self.spi.dr8().read().bits();
+        while self.spi.sr().read().bsy().bit_is_set() {}
+        if self.spi.sr().read().crcerr().bit_is_set() {
+            Err(Error::Crc)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Waits until the TX FIFO is empty and the bus idle, then drains the RX
+    /// FIFO and clears a pending overrun.
+    fn wait_idle(&mut self) {
+        while self.spi.sr().read().ftlvl().bits() != 0 {}
+        while self.spi.sr().read().bsy().bit_is_set() {}
+        while self.spi.sr().read().frlvl().bits() != 0 {
+            let _ = self.spi.dr8().read().bits();
+        }
+        // OVR is cleared by a DR read followed by a SR read
+        let _ = self.spi.dr8().read().bits();
+        let _ = self.spi.sr().read();
+    }
+
+    /// Exchanges `len` frames, keeping up to two frames in flight so the TX
+    /// FIFO never runs dry between bytes. Frames past the end of `write` are
+    /// sent as `dummy` and frames past the end of `read` are discarded. Without a
+    /// `write` buffer the frames are taken from `read`, which is overwritten
+    /// in place. The first `skip` received frames are discarded and the
+    /// following ones stored from the start of `read`. Two frames in flight
+    /// never exceed the RX FIFO, so this cannot overrun.
+    fn exchange(
+        &mut self,
+        read: &mut [u8],
+        write: Option<&[u8]>,
+        len: usize,
+        dummy: u8,
+        skip: usize,
+    ) -> Result<(), Error> {
+        let (mut tx, mut rx) = (0, 0);
+        while rx < len {
+            if tx < len && tx - rx < 2 {
+                let byte = match write {
+                    Some(write) => write.get(tx).copied().unwrap_or(dummy),
+                    None => read[tx],
+                };
+                match self.send_byte(byte) {
+                    Ok(()) => tx += 1,
+                    Err(nb::Error::WouldBlock) => {}
+                    Err(nb::Error::Other(e)) => return Err(e),
+                }
+            }
+            match self.receive_byte() {
+                Ok(byte) => {
+                    if let Some(r) = rx.checked_sub(skip).and_then(|idx| read.get_mut(idx)) {
+                        *r = byte;
+                    }
+                    rx += 1;
+                }
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
     fn receive_byte(&mut self) -> nb::Result<u8, Error> {
         let sr = self.spi.sr().read();
 
@@ -323,7 +1242,12 @@ impl<SPI: Instance, PINS> SpiBus<SPI, PINS> {
         } else if sr.crcerr().bit_is_set() {
             nb::Error::Other(Error::Crc)
         } else if sr.rxne().bit_is_set() {
-            return Ok(self.spi.dr8().read().bits() as u8);
+            let byte = self.spi.dr8().read().bits();
+            #[cfg(feature = "spi-trace")]
+            if let Some(trace) = &mut self.trace {
+                trace.record(TraceEntry::Received(byte));
+            }
+            return Ok(byte);
         } else {
             nb::Error::WouldBlock
         })
@@ -339,6 +1263,10 @@ impl<SPI: Instance, PINS> SpiBus<SPI, PINS> {
             nb::Error::Other(Error::Crc)
         } else if sr.txe().bit_is_set() {
             self.spi.dr8().write(|w| unsafe { w.dr().bits(byte as _) });
+            #[cfg(feature = "spi-trace")]
+            if let Some(trace) = &mut self.trace {
+                trace.record(TraceEntry::Sent(byte));
+            }
             return Ok(());
         } else {
             nb::Error::WouldBlock
@@ -352,49 +1280,25 @@ impl<SPI: Instance, PINS> ErrorType for SpiBus<SPI, PINS> {
 
 impl<SPI: Instance, PINS> spi::SpiBus for SpiBus<SPI, PINS> {
     fn read(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
-        for byte in bytes.iter_mut() {
-            block!(self.send_byte(0))?;
-            *byte = block!(self.receive_byte())?;
-        }
-        Ok(())
+        let len = bytes.len();
+        self.exchange(bytes, Some(&[]), len, 0, 0)
     }
 
     fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
-        for byte in bytes.iter() {
-            block!(self.send_byte(*byte))?;
-            block!(self.receive_byte())?;
+        if self.tx_only {
+            return self.write_tx_only(bytes);
         }
-        Ok(())
+        self.exchange(&mut [], Some(bytes), bytes.len(), 0, 0)
     }
 
     fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
-        let mut iter_r = read.iter_mut();
-        let mut iter_w = write.iter().cloned();
-        loop {
-            match (iter_r.next(), iter_w.next()) {
-                (Some(r), Some(w)) => {
-                    block!(self.send_byte(w))?;
-                    *r = block!(self.receive_byte())?;
-                }
-                (Some(r), None) => {
-                    block!(self.send_byte(0))?;
-                    *r = block!(self.receive_byte())?;
-                }
-                (None, Some(w)) => {
-                    block!(self.send_byte(w))?;
-                    let _ = block!(self.receive_byte())?;
-                }
-                (None, None) => return Ok(()),
-            }
-        }
+        let len = read.len().max(write.len());
+        self.exchange(read, Some(write), len, 0, 0)
     }
 
     fn transfer_in_place(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
-        for byte in bytes.iter_mut() {
-            block!(self.send_byte(*byte))?;
-            *byte = block!(self.receive_byte())?;
-        }
-        Ok(())
+        let len = bytes.len();
+        self.exchange(bytes, None, len, 0, 0)
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
@@ -404,6 +1308,8 @@ impl<SPI: Instance, PINS> spi::SpiBus for SpiBus<SPI, PINS> {
 
 spi!(
     pac::SPI1,
+    SPI1_RX,
+    SPI1_TX,
     sck: [
         (PA1<DefaultMode>, AltFunction::AF0),
         (PA5<DefaultMode>, AltFunction::AF0),
@@ -423,10 +1329,18 @@ spi!(
         (PB5<DefaultMode>, AltFunction::AF0),
         (PD6<DefaultMode>, AltFunction::AF1),
     ],
+    nss: [
+        (PA4<DefaultMode>, AltFunction::AF0),
+        (PA15<DefaultMode>, AltFunction::AF0),
+        (PB0<DefaultMode>, AltFunction::AF0),
+        (PD9<DefaultMode>, AltFunction::AF1),
+    ],
 );
 
 spi!(
     pac::SPI2,
+    SPI2_RX,
+    SPI2_TX,
     sck: [
         (PA0<DefaultMode>, AltFunction::AF0),
         (PB8<DefaultMode>, AltFunction::AF1),
@@ -452,4 +1366,9 @@ spi!(
         (PC3<DefaultMode>, AltFunction::AF1),
         (PD4<DefaultMode>, AltFunction::AF1),
     ],
+    nss: [
+        (PB9<DefaultMode>, AltFunction::AF5),
+        (PB12<DefaultMode>, AltFunction::AF0),
+        (PD0<DefaultMode>, AltFunction::AF1),
+    ],
 );
@@ -0,0 +1,246 @@
+//! # Async SPI
+//!
+//! `embedded_hal_async::spi::SpiBus` on top of two DMA channels, available
+//! with the `async` feature. Every operation runs as a DMA transfer and the
+//! waiting task is woken from the DMA interrupt instead of busy-waiting.
+//!
+//! Executor requirements: the task awaiting the bus must be polled again when
+//! its waker fires, and the interrupts of both DMA channels must be unmasked
+//! in the NVIC with a handler that calls [`on_dma_interrupt`]:
+//!
+//! ```ignore
+//! let dma = dp.DMA1.split(&mut rcc, dp.DMAMUX);
+//! let mut spi = spi.into_async(dma.ch2, dma.ch3);
+//! spi.transfer(&mut rx, &tx).await?;
+//!
+//! #[interrupt]
+//! fn DMA_CHANNEL2_3() {
+//!     stm32g0xx_hal::spi::asynch::on_dma_interrupt();
+//! }
+//! ```
+//!
+//! Dropping a pending future stops both channels; the frames already in
+//! flight are discarded at the start of the next operation.
+use super::{Error, ErrorType, Instance, SpiBus};
+use crate::dma::{Channel, Direction, Event, WordSize, C};
+use crate::stm32::DMA1;
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::{Poll, Waker};
+use cortex_m::interrupt::Mutex;
+
+#[cfg(any(
+    feature = "stm32g070",
+    feature = "stm32g071",
+    feature = "stm32g081",
+    feature = "stm32g0b1",
+    feature = "stm32g0c1",
+))]
+const CHANNELS: usize = 7;
+#[cfg(any(feature = "stm32g030", feature = "stm32g031", feature = "stm32g041"))]
+const CHANNELS: usize = 5;
+
+/// Waker of the task waiting on each DMA channel
+static WAKERS: [Mutex<RefCell<Option<Waker>>>; CHANNELS] =
+    [const { Mutex::new(RefCell::new(None)) }; CHANNELS];
+
+/// Services the DMA interrupt for the async SPI buses. Masks the interrupts
+/// of the finished channels and wakes the tasks waiting on them. Call it from
+/// the handler of every DMA interrupt used by an [`AsyncSpi`].
+pub fn on_dma_interrupt() {
+    // NOTE(unsafe) only the interrupt enable bits of finished channels are
+    // cleared, their flags are left for the waiting task
+    let dma = unsafe { &*DMA1::ptr() };
+    let isr = dma.isr().read();
+    for (n, waker) in WAKERS.iter().enumerate() {
+        let ch = dma.ch(n);
+        let cr = ch.cr().read();
+        let armed = cr.tcie().bit_is_set() || cr.teie().bit_is_set();
+        let done = isr.tcif(n as u8).bit_is_set() || isr.teif(n as u8).bit_is_set();
+        if armed && done {
+            ch.cr()
+                .modify(|_, w| w.tcie().clear_bit().teie().clear_bit());
+            if let Some(waker) = cortex_m::interrupt::free(|cs| waker.borrow(cs).take()) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// SPI bus driven by the DMA channels `C<TX>` and `C<RX>`, see
+/// [`SpiBus::into_async`]
+pub struct AsyncSpi<SPI: Instance, PINS, const TX: u8, const RX: u8> {
+    bus: SpiBus<SPI, PINS>,
+    tx: C<TX>,
+    rx: C<RX>,
+}
+
+impl<SPI: Instance, PINS> SpiBus<SPI, PINS> {
+    /// Converts the bus to an async bus transferring 8 bit frames with the
+    /// `tx` and `rx` DMA channels
+    pub fn into_async<const TX: u8, const RX: u8>(
+        self,
+        mut tx: C<TX>,
+        mut rx: C<RX>,
+    ) -> AsyncSpi<SPI, PINS, TX, RX> {
+        let dr = self.spi.dr().as_ptr() as u32;
+        tx.disable();
+        tx.set_peripheral_address(dr, false);
+        tx.set_direction(Direction::FromMemory);
+        tx.set_word_size(WordSize::BITS8);
+        tx.set_circular_mode(false);
+        tx.select_peripheral(SPI::dmamux_tx());
+        rx.disable();
+        rx.set_peripheral_address(dr, false);
+        rx.set_direction(Direction::FromPeripheral);
+        rx.set_word_size(WordSize::BITS8);
+        rx.set_circular_mode(false);
+        rx.select_peripheral(SPI::dmamux_rx());
+        AsyncSpi { bus: self, tx, rx }
+    }
+}
+
+impl<SPI: Instance, PINS, const TX: u8, const RX: u8> AsyncSpi<SPI, PINS, TX, RX> {
+    /// Returns the blocking bus, e.g. to change its settings
+    pub fn bus(&mut self) -> &mut SpiBus<SPI, PINS> {
+        &mut self.bus
+    }
+
+    /// Releases the blocking bus and the DMA channels
+    pub fn release(self) -> (SpiBus<SPI, PINS>, C<TX>, C<RX>) {
+        (self.bus, self.tx, self.rx)
+    }
+
+    /// Exchanges `len` frames, receiving into `read` and sending from
+    /// `write`. A buffer that is not incremented is read or written for
+    /// every frame.
+    async fn dma(
+        &mut self,
+        read: (*mut u8, bool),
+        write: (*const u8, bool),
+        len: usize,
+    ) -> Result<(), Error> {
+        let mut done = 0;
+        while done < len {
+            let chunk = (len - done).min(u16::MAX as usize);
+            let offset = |inc: bool| if inc { done } else { 0 };
+            let rx_addr = read.0.wrapping_add(offset(read.1)) as u32;
+            let tx_addr = write.0.wrapping_add(offset(write.1)) as u32;
+
+            let mut transfer = Transfer::start(self, (rx_addr, read.1), (tx_addr, write.1), chunk);
+            poll_fn(|cx| transfer.poll(cx.waker())).await?;
+            done += chunk;
+        }
+        Ok(())
+    }
+}
+
+/// A running DMA transfer, stopped on drop
+struct Transfer<'a, SPI: Instance, PINS, const TX: u8, const RX: u8> {
+    spi: &'a mut AsyncSpi<SPI, PINS, TX, RX>,
+}
+
+impl<'a, SPI: Instance, PINS, const TX: u8, const RX: u8> Transfer<'a, SPI, PINS, TX, RX> {
+    fn start(
+        spi: &'a mut AsyncSpi<SPI, PINS, TX, RX>,
+        read: (u32, bool),
+        write: (u32, bool),
+        len: usize,
+    ) -> Self {
+        spi.bus.clear_rx_fifo();
+        spi.rx.set_memory_address(read.0, read.1);
+        spi.rx.set_transfer_length(len as u16);
+        spi.tx.set_memory_address(write.0, write.1);
+        spi.tx.set_transfer_length(len as u16);
+        spi.rx.listen(Event::TransferComplete);
+        spi.rx.listen(Event::TransferError);
+        spi.tx.listen(Event::TransferError);
+
+        // RM0444 SPI communication using DMA: enable RX requests before the
+        // channels, and TX requests last
+        spi.bus.spi.cr2().modify(|_, w| w.rxdmaen().set_bit());
+        spi.rx.enable();
+        spi.tx.enable();
+        spi.bus.spi.cr2().modify(|_, w| w.txdmaen().set_bit());
+        Transfer { spi }
+    }
+
+    fn poll(&mut self, waker: &Waker) -> Poll<Result<(), Error>> {
+        cortex_m::interrupt::free(|cs| {
+            let mut slot = WAKERS[RX as usize].borrow(cs).borrow_mut();
+            if !slot.as_ref().is_some_and(|w| w.will_wake(waker)) {
+                *slot = Some(waker.clone());
+            }
+        });
+        let (tx, rx) = (&self.spi.tx, &self.spi.rx);
+        if tx.event_occurred(Event::TransferError) || rx.event_occurred(Event::TransferError) {
+            Poll::Ready(Err(Error::Dma))
+        } else if rx.event_occurred(Event::TransferComplete) {
+            // the last frame is received after it was sent, so the bus is idle
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<SPI: Instance, PINS, const TX: u8, const RX: u8> Drop for Transfer<'_, SPI, PINS, TX, RX> {
+    fn drop(&mut self) {
+        let spi = &mut *self.spi;
+        spi.bus
+            .spi
+            .cr2()
+            .modify(|_, w| w.txdmaen().clear_bit().rxdmaen().clear_bit());
+        spi.tx.disable();
+        spi.rx.disable();
+        spi.tx.unlisten(Event::Any);
+        spi.rx.unlisten(Event::Any);
+        cortex_m::interrupt::free(|cs| WAKERS[RX as usize].borrow(cs).take());
+    }
+}
+
+impl<SPI: Instance, PINS, const TX: u8, const RX: u8> ErrorType for AsyncSpi<SPI, PINS, TX, RX> {
+    type Error = Error;
+}
+
+impl<SPI: Instance, PINS, const TX: u8, const RX: u8> embedded_hal_async::spi::SpiBus
+    for AsyncSpi<SPI, PINS, TX, RX>
+{
+    async fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        let dummy = 0u8;
+        let len = words.len();
+        self.dma((words.as_mut_ptr(), true), (&dummy, false), len)
+            .await
+    }
+
+    async fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        let mut sink = 0u8;
+        self.dma((&mut sink, false), (words.as_ptr(), true), words.len())
+            .await
+    }
+
+    async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let common = read.len().min(write.len());
+        let (read, read_tail) = read.split_at_mut(common);
+        let (write, write_tail) = write.split_at(common);
+        self.dma((read.as_mut_ptr(), true), (write.as_ptr(), true), common)
+            .await?;
+        if !read_tail.is_empty() {
+            self.read(read_tail).await
+        } else {
+            self.write(write_tail).await
+        }
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        // every frame is sent before its reply is received into the same byte
+        let len = words.len();
+        let ptr = words.as_mut_ptr();
+        self.dma((ptr, true), (ptr, true), len).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        while self.bus.spi.sr().read().bsy().bit_is_set() {}
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+//! # Bit-banged SPI
+//!
+//! Software SPI master on arbitrary GPIO pins, for when no hardware SPI is
+//! mapped to the pins or all SPI peripherals are in use. The clock is timed
+//! with a `DelayNs` provider, so the actual rate is below the requested one.
+use super::{Mode, Phase, Polarity};
+use crate::time::Hertz;
+use core::convert::Infallible;
+use hal::delay::DelayNs;
+use hal::digital::{InputPin, OutputPin, PinState};
+use hal::spi::{self, ErrorType};
+
+/// Software SPI master
+pub struct BitBangSpi<SCK, MOSI, MISO, DELAY> {
+    sck: SCK,
+    mosi: MOSI,
+    miso: MISO,
+    delay: DELAY,
+    mode: Mode,
+    half_period_ns: u32,
+    lsb_first: bool,
+}
+
+impl<SCK, MOSI, MISO, DELAY> BitBangSpi<SCK, MOSI, MISO, DELAY>
+where
+    SCK: OutputPin<Error = Infallible>,
+    MOSI: OutputPin<Error = Infallible>,
+    MISO: InputPin<Error = Infallible>,
+    DELAY: DelayNs,
+{
+    /// Creates the bus and drives SCK to its idle level
+    pub fn new(sck: SCK, mosi: MOSI, miso: MISO, delay: DELAY, mode: Mode, freq: Hertz) -> Self {
+        assert!(freq.raw() > 0);
+        let mut spi = BitBangSpi {
+            sck,
+            mosi,
+            miso,
+            delay,
+            mode,
+            half_period_ns: 500_000_000 / freq.raw(),
+            lsb_first: false,
+        };
+        spi.set_sck(false);
+        spi
+    }
+
+    /// Shifts the least significant bit first
+    pub fn lsb_first(mut self) -> Self {
+        self.lsb_first = true;
+        self
+    }
+
+    /// Releases the pins and the delay provider
+    pub fn release(self) -> (SCK, MOSI, MISO, DELAY) {
+        (self.sck, self.mosi, self.miso, self.delay)
+    }
+
+    /// Drives SCK to the active (`true`) or idle level
+    fn set_sck(&mut self, active: bool) {
+        let idle_high = self.mode.polarity == Polarity::IdleHigh;
+        let _ = self.sck.set_state(PinState::from(active != idle_high));
+    }
+
+    fn exchange_byte(&mut self, out: u8) -> u8 {
+        let mut input = 0;
+        for i in 0..8 {
+            let shift = if self.lsb_first { i } else { 7 - i };
+            let bit = PinState::from(out & (1 << shift) != 0);
+            let sample = if self.mode.phase == Phase::CaptureOnFirstTransition {
+                let _ = self.mosi.set_state(bit);
+                self.delay.delay_ns(self.half_period_ns);
+                self.set_sck(true);
+                let sample = self.miso.is_high();
+                self.delay.delay_ns(self.half_period_ns);
+                self.set_sck(false);
+                sample
+            } else {
+                self.set_sck(true);
+                let _ = self.mosi.set_state(bit);
+                self.delay.delay_ns(self.half_period_ns);
+                self.set_sck(false);
+                let sample = self.miso.is_high();
+                self.delay.delay_ns(self.half_period_ns);
+                sample
+            };
+            if sample == Ok(true) {
+                input |= 1 << shift;
+            }
+        }
+        input
+    }
+}
+
+impl<SCK, MOSI, MISO, DELAY> ErrorType for BitBangSpi<SCK, MOSI, MISO, DELAY> {
+    type Error = Infallible;
+}
+
+impl<SCK, MOSI, MISO, DELAY> spi::SpiBus for BitBangSpi<SCK, MOSI, MISO, DELAY>
+where
+    SCK: OutputPin<Error = Infallible>,
+    MOSI: OutputPin<Error = Infallible>,
+    MISO: InputPin<Error = Infallible>,
+    DELAY: DelayNs,
+{
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        for word in words.iter_mut() {
+            *word = self.exchange_byte(0);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        for word in words.iter() {
+            self.exchange_byte(*word);
+        }
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        for idx in 0..read.len().max(write.len()) {
+            let byte = self.exchange_byte(write.get(idx).copied().unwrap_or(0));
+            if let Some(r) = read.get_mut(idx) {
+                *r = byte;
+            }
+        }
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        for word in words.iter_mut() {
+            *word = self.exchange_byte(*word);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
@@ -1,13 +1,161 @@
+//! Time units
+//!
+//! `Hertz`, `MicroSecond` and friends are `fugit` types, so they can be passed
+//! to `fugit` based schedulers and RTIC monotonics without conversion. Other
+//! tick rates are reached with `fugit`'s `convert()`, e.g.
+//! `let ticks: fugit::TimerDurationU32<32_768> = timeout.convert();`
+//!
+//! `fugit` prints the raw value with the base unit, e.g. `16000000 Hz`. For
+//! logs, `clk.scaled()` picks a readable unit instead, e.g. `16 MHz`.
+
+// The `fugit` types provide const constructors (`from_raw`, `from_ticks`),
+// `Add`/`Sub`/`Mul`/`Div` and `Ord`.
 pub use fugit::{
     ExtU32, HertzU32 as Hertz, HoursDurationU32 as Hour, MicrosDurationU32 as MicroSecond,
     MinutesDurationU32 as Minute, RateExtU32, SecsDurationU32 as Second,
 };
 
+/// Formats a frequency or duration with a scaled unit, e.g. `16 MHz`,
+/// `1.5 kHz` or `250 µs`, through `Display` and `defmt::Format`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Scaled<T>(pub T);
+
+pub trait ScaledExt: Sized {
+    /// Wraps the value in `Scaled` for logging
+    fn scaled(self) -> Scaled<Self>;
+}
+
+impl ScaledExt for Hertz {
+    fn scaled(self) -> Scaled<Self> {
+        Scaled(self)
+    }
+}
+
+impl ScaledExt for MicroSecond {
+    fn scaled(self) -> Scaled<Self> {
+        Scaled(self)
+    }
+}
+
+const HERTZ_UNITS: [(u32, &str); 3] = [(1, "Hz"), (1_000, "kHz"), (1_000_000, "MHz")];
+const MICROSECOND_UNITS: [(u32, &str); 3] = [(1, "µs"), (1_000, "ms"), (1_000_000, "s")];
+
+/// A value split in its integer part and up to 3 fractional digits
+struct ScaledParts {
+    int: u32,
+    frac: [u8; 3],
+    digits: usize,
+    unit: &'static str,
+}
+
+impl ScaledParts {
+    fn new(raw: u32, units: &[(u32, &'static str); 3]) -> Self {
+        let (div, unit) = units
+            .iter()
+            .rev()
+            .find(|(div, _)| raw >= *div)
+            .copied()
+            .unwrap_or(units[0]);
+        let mut rem = raw % div;
+        let mut scale = div;
+        let mut frac = [0; 3];
+        let mut digits = 0;
+        while digits < 3 && rem != 0 {
+            scale /= 10;
+            frac[digits] = (rem / scale) as u8;
+            rem %= scale;
+            digits += 1;
+        }
+        while digits > 0 && frac[digits - 1] == 0 {
+            digits -= 1;
+        }
+        ScaledParts {
+            int: raw / div,
+            frac,
+            digits,
+            unit,
+        }
+    }
+
+    fn of_hertz(hz: Hertz) -> Self {
+        Self::new(hz.raw(), &HERTZ_UNITS)
+    }
+
+    fn of_microseconds(us: MicroSecond) -> Self {
+        Self::new(us.ticks(), &MICROSECOND_UNITS)
+    }
+}
+
+impl core::fmt::Display for ScaledParts {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.int)?;
+        if self.digits > 0 {
+            f.write_str(".")?;
+            for digit in &self.frac[..self.digits] {
+                write!(f, "{}", digit)?;
+            }
+        }
+        write!(f, " {}", self.unit)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ScaledParts {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=u32}", self.int);
+        if self.digits > 0 {
+            defmt::write!(f, ".");
+            for digit in &self.frac[..self.digits] {
+                defmt::write!(f, "{=u8}", *digit);
+            }
+        }
+        defmt::write!(f, " {=str}", self.unit);
+    }
+}
+
+impl core::fmt::Display for Scaled<Hertz> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        ScaledParts::of_hertz(self.0).fmt(f)
+    }
+}
+
+impl core::fmt::Display for Scaled<MicroSecond> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        ScaledParts::of_microseconds(self.0).fmt(f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Scaled<Hertz> {
+    fn format(&self, f: defmt::Formatter) {
+        ScaledParts::of_hertz(self.0).format(f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Scaled<MicroSecond> {
+    fn format(&self, f: defmt::Formatter) {
+        ScaledParts::of_microseconds(self.0).format(f)
+    }
+}
+
 /// Baudrate
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug, Eq, PartialEq, PartialOrd, Clone, Copy)]
+#[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Clone, Copy)]
 pub struct Bps(pub u32);
 
+impl Bps {
+    /// Creates a baudrate from a raw bits per second value
+    pub const fn from_raw(bps: u32) -> Self {
+        Bps(bps)
+    }
+
+    /// Returns the baudrate in bits per second
+    pub const fn raw(self) -> u32 {
+        self.0
+    }
+}
+
 /// A measurement of a monotonically nondecreasing clock
 pub type Instant = fugit::TimerInstantU32<1_000_000>;
 
@@ -0,0 +1,91 @@
+//! # Dual edge capture
+//!
+//! Measures the high and low time of a signal on channel 1 of a timer. Both
+//! capture channels are mapped to TI1: CC1 captures the rising edges and
+//! resets the counter, CC2 captures the falling edges. CCR1 then holds the
+//! period and CCR2 the high time of the last complete period.
+//!
+//! ```ignore
+//! let tim = dp.TIM3.timer(&mut rcc);
+//! let mut capture = DualEdgeCapture::new(tim, gpioa.pa6, 10.millis());
+//! let (high, low) = nb::block!(capture.wait()).unwrap();
+//! ```
+use super::pins::TimerPin;
+use super::{Channel1, Timer};
+use crate::stm32::*;
+use crate::time::MicroSecond;
+use void::Void;
+
+/// High and low time capture of the signal on channel 1
+pub struct DualEdgeCapture<TIM, PIN> {
+    tim: Timer<TIM>,
+    pin: PIN,
+    // false until a full period was captured since start or an overflow
+    valid: bool,
+}
+
+macro_rules! dual_edge_capture {
+    ($($TIM:ident: $max_arr:expr,)+) => {
+        $(
+            impl<PIN: TimerPin<$TIM, Channel = Channel1>> DualEdgeCapture<$TIM, PIN> {
+                /// Starts capturing `pin`. The counter resolution is the
+                /// finest one that fits periods up to `max_period`; longer
+                /// periods are discarded.
+                pub fn new(mut tim: Timer<$TIM>, pin: PIN, max_period: MicroSecond) -> Self {
+                    let cycles = tim.clk.raw() as u64 * max_period.ticks() as u64 / 1_000_000;
+                    let psc = (cycles / ($max_arr as u64 + 1)).min(0xffff);
+                    // CC1S = 01 (TI1), CC2S = 10 (TI1), CC1 on rising edges,
+                    // CC2 on falling edges (CC2P)
+                    tim.start_reset_capture(psc as u32, $max_arr, 0b01 | (0b10 << 8), 0b0011_0001);
+                    pin.setup();
+
+                    DualEdgeCapture {
+                        tim,
+                        pin,
+                        valid: false,
+                    }
+                }
+
+                /// Returns the high and low time of the last period once a
+                /// new period has been captured
+                pub fn wait(&mut self) -> nb::Result<(MicroSecond, MicroSecond), Void> {
+                    let t = &self.tim.tim;
+                    let sr = t.sr().read();
+                    if sr.uif().bit_is_set() {
+                        // the next capture measures a wrapped counter
+                        t.sr().modify(|_, w| w.uif().clear_bit().cc1if().clear_bit());
+                        self.valid = false;
+                        return Err(nb::Error::WouldBlock);
+                    }
+                    if sr.cc1if().bit_is_clear() {
+                        return Err(nb::Error::WouldBlock);
+                    }
+                    // reading CCR1 clears CC1IF
+                    let period = t.ccr(0).read().bits();
+                    let high = t.ccr(1).read().bits().min(period);
+                    if !core::mem::replace(&mut self.valid, true) {
+                        // the first capture started with the counter, not an edge
+                        return Err(nb::Error::WouldBlock);
+                    }
+                    Ok((self.tim.ticks_to_us(high), self.tim.ticks_to_us(period - high)))
+                }
+
+                /// Stops the timer and releases it and the pin
+                pub fn release(self) -> (Timer<$TIM>, PIN) {
+                    self.tim.tim.cr1().modify(|_, w| w.cen().clear_bit());
+                    (self.tim, self.pin.release())
+                }
+            }
+        )+
+    }
+}
+
+dual_edge_capture! {
+    TIM1: 0xffff,
+    TIM3: 0xffff,
+}
+
+#[cfg(feature = "stm32g0x1")]
+dual_edge_capture! {
+    TIM2: 0xffff_ffff_u32,
+}
@@ -0,0 +1,86 @@
+//! # Debounced input
+//!
+//! Samples a GPIO input on every tick of a periodic timer and only reports a
+//! new state after it was read the same number of consecutive times:
+//!
+//! ```ignore
+//! let mut button = DebouncedInput::new(gpioc.pc13.into_pull_up_input(), true, 5);
+//! timer.start(2.millis());
+//! timer.listen();
+//!
+//! // in the timer interrupt
+//! timer.clear_irq();
+//! if button.update() == Some(Edge::Pressed) {
+//!     // button pressed
+//! }
+//! ```
+use hal::digital::InputPin;
+
+/// Change of the debounced state
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Edge {
+    /// The input became active
+    Pressed,
+    /// The input became inactive
+    Released,
+}
+
+/// GPIO input reporting a state once it was stable for a number of samples
+pub struct DebouncedInput<PIN> {
+    pin: PIN,
+    active_low: bool,
+    samples: u8,
+    count: u8,
+    pressed: bool,
+}
+
+impl<PIN: InputPin> DebouncedInput<PIN> {
+    /// Wraps `pin`, which is pressed when low if `active_low` is set. A new
+    /// state is reported after `samples` consecutive identical reads.
+    pub fn new(pin: PIN, active_low: bool, samples: u8) -> Self {
+        assert!(samples > 0);
+        DebouncedInput {
+            pin,
+            active_low,
+            samples,
+            count: 0,
+            pressed: false,
+        }
+    }
+
+    /// Samples the pin, call this from the periodic timer tick. Returns the
+    /// edge if the debounced state changed.
+    pub fn update(&mut self) -> Option<Edge> {
+        let level = self
+            .pin
+            .is_high()
+            .unwrap_or(self.pressed != self.active_low);
+        let pressed = level != self.active_low;
+        if pressed == self.pressed {
+            self.count = 0;
+            return None;
+        }
+        self.count += 1;
+        if self.count < self.samples {
+            return None;
+        }
+        self.count = 0;
+        self.pressed = pressed;
+        Some(if pressed {
+            Edge::Pressed
+        } else {
+            Edge::Released
+        })
+    }
+
+    /// Returns the debounced state
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Releases the pin
+    pub fn release(self) -> PIN {
+        self.pin
+    }
+}
@@ -29,16 +29,8 @@ impl Delay<SYST> {
     }
 
     pub fn delay(&mut self, delay: MicroSecond) {
-        let mut cycles = crate::time::cycles(delay, self.clk);
-        while cycles > 0 {
-            let reload = cmp::min(cycles, 0x00ff_ffff);
-            cycles -= reload;
-            self.tim.set_reload(reload);
-            self.tim.clear_current();
-            self.tim.enable_counter();
-            while !self.tim.has_wrapped() {}
-            self.tim.disable_counter();
-        }
+        let cycles = crate::time::cycles(delay, self.clk);
+        syst_delay(&mut self.tim, cycles as u64);
     }
 
     /// Releases the system timer (SysTick) resource
@@ -47,6 +39,21 @@ impl Delay<SYST> {
     }
 }
 
+/// Busy waits `cycles` SysTick clock cycles, looping over the 24 bit reload.
+/// The counter is left stopped.
+pub(super) fn syst_delay(syst: &mut SYST, mut cycles: u64) {
+    syst.disable_counter();
+    while cycles > 0 {
+        let reload = cmp::min(cycles, 0x00ff_ffff);
+        cycles -= reload;
+        syst.set_reload(reload as u32);
+        syst.clear_current();
+        syst.enable_counter();
+        while !syst.has_wrapped() {}
+        syst.disable_counter();
+    }
+}
+
 impl DelayNs for Delay<SYST> {
     fn delay_ns(&mut self, ns: u32) {
         self.delay(ns.nanos())
@@ -0,0 +1,75 @@
+//! # Dithered PWM
+//!
+//! Adds `FRAC_BITS` bits of resolution to a PWM channel by alternating the
+//! compare value between two adjacent duty cycles across successive periods.
+//! Call [`DitheredPwm::update`] from the update interrupt of the PWM timer,
+//! with CCR preload enabled so each value applies to a whole period:
+//!
+//! ```ignore
+//! let mut led = DitheredPwm::new(pwm.bind_pin(gpioa.pa8));
+//! pwm.listen();
+//! led.set_level(1000); // a compare value of 3.90625 on average
+//!
+//! // in the timer interrupt
+//! pwm.clear_irq();
+//! led.update();
+//! ```
+use embedded_hal::pwm::SetDutyCycle;
+
+/// Number of fractional bits of the dithered level
+pub const FRAC_BITS: u32 = 8;
+
+const FRAC_MASK: u32 = (1 << FRAC_BITS) - 1;
+
+/// PWM channel with a first order sigma delta on the duty cycle
+pub struct DitheredPwm<PWM> {
+    pwm: PWM,
+    level: u32,
+    accumulator: u32,
+}
+
+impl<PWM: SetDutyCycle> DitheredPwm<PWM> {
+    /// Wraps `pwm`, starting fully off
+    pub fn new(mut pwm: PWM) -> Self {
+        let _ = pwm.set_duty_cycle_fully_off();
+        DitheredPwm {
+            pwm,
+            level: 0,
+            accumulator: 0,
+        }
+    }
+
+    /// Returns the highest level, `max_duty_cycle() << FRAC_BITS`
+    pub fn max_level(&self) -> u32 {
+        (self.pwm.max_duty_cycle() as u32) << FRAC_BITS
+    }
+
+    /// Sets the duty cycle in 1 / 2^`FRAC_BITS` steps of the channel
+    /// resolution, saturating at `max_level()`
+    pub fn set_level(&mut self, level: u32) {
+        self.level = level.min(self.max_level());
+    }
+
+    /// Returns the current level
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// Writes the compare value for the next period, call this once per
+    /// period from the update interrupt
+    pub fn update(&mut self) {
+        let max = self.pwm.max_duty_cycle() as u32;
+        let mut duty = self.level >> FRAC_BITS;
+        self.accumulator += self.level & FRAC_MASK;
+        if self.accumulator > FRAC_MASK {
+            self.accumulator &= FRAC_MASK;
+            duty = (duty + 1).min(max);
+        }
+        let _ = self.pwm.set_duty_cycle(duty as u16);
+    }
+
+    /// Releases the PWM channel
+    pub fn release(self) -> PWM {
+        self.pwm
+    }
+}
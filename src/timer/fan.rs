@@ -0,0 +1,110 @@
+//! # PWM fan controller
+//!
+//! Drives a 4-pin PWM fan from a PWM channel (25 kHz is the usual fan PWM
+//! frequency) and measures its tachometer on channel 1 of a second timer. The
+//! tach timer runs in PWM input style: every tach edge captures the counter
+//! into CCR1 and resets it, so CCR1 holds the time between two tach pulses.
+//!
+//! ```ignore
+//! let pwm = dp.TIM1.pwm(25.kHz(), &mut rcc);
+//! let fan_pwm = pwm.bind_pin(gpioa.pa8);
+//! let tach = dp.TIM3.timer(&mut rcc);
+//! let mut fan = FanController::new(fan_pwm, tach, gpioa.pa6, 2);
+//! fan.set_speed(60);
+//! let rpm = fan.rpm();
+//! ```
+use super::pins::TimerPin;
+use super::{Channel1, Timer};
+use crate::stm32::*;
+use embedded_hal::pwm::SetDutyCycle;
+
+/// Tach counter clock, a tach period of up to 0.65 s fits the 16 bit counter
+const TACH_CLOCK: u32 = 100_000;
+
+/// PWM fan with tachometer feedback
+pub struct FanController<PWM, TIM, PIN> {
+    pwm: PWM,
+    tach: Timer<TIM>,
+    pin: PIN,
+    pulses_per_rev: u8,
+    period: Option<u16>,
+    // false until a full tach period was captured since start or an overflow
+    valid: bool,
+}
+
+macro_rules! fan_controller {
+    ($($TIM:ident,)+) => {
+        $(
+            impl<PWM: SetDutyCycle, PIN: TimerPin<$TIM, Channel = Channel1>> FanController<PWM, $TIM, PIN> {
+                /// Creates the controller. `tach` measures the tach signal on
+                /// `pin`, which gives `pulses_per_rev` pulses per revolution
+                /// (2 for most fans). The fan is stopped.
+                pub fn new(mut pwm: PWM, mut tach: Timer<$TIM>, pin: PIN, pulses_per_rev: u8) -> Self {
+                    assert!(pulses_per_rev > 0);
+                    let _ = pwm.set_duty_cycle_fully_off();
+
+                    let psc = (tach.clk.raw() / TACH_CLOCK).clamp(1, 0x1_0000) - 1;
+                    // CC1S = 01 (TI1) with the strongest input filter against
+                    // tach noise, capture on rising edges
+                    tach.start_reset_capture(psc, 0xffff, 0b01 | (0b1111 << 4), 0b1);
+                    pin.setup();
+
+                    FanController {
+                        pwm,
+                        tach,
+                        pin,
+                        pulses_per_rev,
+                        period: None,
+                        valid: false,
+                    }
+                }
+
+                /// Sets the fan speed in percent of the full PWM duty cycle
+                pub fn set_speed(&mut self, percent: u8) {
+                    let _ = self.pwm.set_duty_cycle_percent(percent.min(100));
+                }
+
+                /// Returns the fan speed in revolutions per minute, 0 if no
+                /// tach pulse arrived for 0.65 s
+                pub fn rpm(&mut self) -> u32 {
+                    let tim = &self.tach.tim;
+                    let sr = tim.sr().read();
+                    if sr.uif().bit_is_set() {
+                        // the next capture measures a wrapped counter
+                        tim.sr().modify(|_, w| w.uif().clear_bit().cc1if().clear_bit());
+                        self.period = None;
+                        self.valid = false;
+                    } else if sr.cc1if().bit_is_set() {
+                        // reading CCR1 clears CC1IF
+                        let period = tim.ccr(0).read().bits() as u16;
+                        if core::mem::replace(&mut self.valid, true) {
+                            self.period = Some(period);
+                        }
+                    }
+                    match self.period {
+                        Some(period) if period > 0 => {
+                            60 * TACH_CLOCK / (period as u32 * self.pulses_per_rev as u32)
+                        }
+                        _ => 0,
+                    }
+                }
+
+                /// Stops the tach timer and releases the parts
+                pub fn release(self) -> (PWM, Timer<$TIM>, PIN) {
+                    self.tach.tim.cr1().modify(|_, w| w.cen().clear_bit());
+                    (self.pwm, self.tach, self.pin.release())
+                }
+            }
+        )+
+    }
+}
+
+fan_controller! {
+    TIM1,
+    TIM3,
+}
+
+#[cfg(feature = "stm32g0x1")]
+fan_controller! {
+    TIM2,
+}
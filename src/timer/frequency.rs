@@ -0,0 +1,186 @@
+//! # Frequency counter
+//!
+//! Counts the pulses of an external signal during a precise time window. The
+//! counter timer is clocked by its ETR input (external clock mode 2) and gated
+//! by the TRGO of a second timer, which runs a single window in one-pulse mode
+//! with its counter enable as TRGO. The ITRx input of the counter timer that
+//! carries the TRGO of the gate timer is listed in the reference manual,
+//! section "TIMx internal trigger connection".
+//!
+//! The counter does not overflow during the window as long as the number of
+//! pulses fits its counter (16 bit, or 32 bit for TIM2).
+use super::pins::EtrPin;
+use super::{smcr_slave_bits, MasterMode, SlaveMode, Timer, TriggerSource, SMCR_SLAVE_MASK};
+use crate::stm32::*;
+use crate::time::{Hertz, MicroSecond};
+use void::Void;
+
+/// ECE bit of SMCR, external clock mode 2
+const SMCR_ECE: u32 = 1 << 14;
+
+/// Timer counting the ETR pulses while its trigger input is high
+pub trait PulseCounter {
+    /// Clears the counter and counts ETR pulses while `trigger` is high
+    fn start_counting(&mut self, trigger: TriggerSource);
+    /// Returns the number of pulses counted
+    fn pulses(&self) -> u32;
+}
+
+/// Timer generating a single gate window on its TRGO
+pub trait GateWindow {
+    /// Starts a window of about `window`, returns its exact length in timer
+    /// clock cycles
+    fn start_window(&mut self, window: MicroSecond) -> u64;
+    /// Returns true while the window is open
+    fn window_open(&self) -> bool;
+    /// Returns the timer clock
+    fn clock(&self) -> Hertz;
+}
+
+/// Measures a frequency by counting pulses during a gate window
+pub struct FrequencyCounter<COUNTER, GATE> {
+    counter: COUNTER,
+    gate: GATE,
+    trigger: TriggerSource,
+    window: MicroSecond,
+    window_cycles: u64,
+}
+
+impl<TIM, GATE: GateWindow> FrequencyCounter<Timer<TIM>, GATE>
+where
+    Timer<TIM>: PulseCounter,
+{
+    /// Creates the counter. The signal is counted on the ETR input `pin` of
+    /// `counter`, `trigger` is the ITRx input of `counter` that is connected
+    /// to the TRGO of `gate`.
+    pub fn new<P: EtrPin<TIM>>(
+        counter: Timer<TIM>,
+        gate: GATE,
+        pin: P,
+        trigger: TriggerSource,
+        window: MicroSecond,
+    ) -> Self {
+        pin.setup();
+        FrequencyCounter {
+            counter,
+            gate,
+            trigger,
+            window,
+            window_cycles: 0,
+        }
+    }
+}
+
+impl<COUNTER: PulseCounter, GATE: GateWindow> FrequencyCounter<COUNTER, GATE> {
+    /// Starts a measurement
+    pub fn start(&mut self) {
+        self.counter.start_counting(self.trigger);
+        self.window_cycles = self.gate.start_window(self.window);
+    }
+
+    /// Returns the measured frequency once the window has closed
+    pub fn wait(&mut self) -> nb::Result<Hertz, Void> {
+        if self.window_cycles == 0 || self.gate.window_open() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let pulses = self.counter.pulses() as u64;
+        let freq = pulses * self.gate.clock().raw() as u64 / self.window_cycles;
+        Ok(Hertz::from_raw(freq as u32))
+    }
+
+    /// Starts a measurement and blocks until the frequency is known
+    pub fn measure(&mut self) -> Hertz {
+        self.start();
+        let Ok(freq) = nb::block!(self.wait());
+        freq
+    }
+
+    /// Releases the timers
+    pub fn release(self) -> (COUNTER, GATE) {
+        (self.counter, self.gate)
+    }
+}
+
+macro_rules! pulse_counter {
+    ($($TIM:ident: $max_arr:expr,)+) => {
+        $(
+            impl PulseCounter for Timer<$TIM> {
+                fn start_counting(&mut self, trigger: TriggerSource) {
+                    self.tim.cr1().modify(|_, w| w.cen().clear_bit());
+                    self.tim.psc().write(|w| unsafe { w.bits(0) });
+                    self.tim.arr().write(|w| unsafe { w.bits($max_arr) });
+                    self.tim.egr().write(|w| w.ug().set_bit());
+                    let bits = smcr_slave_bits(trigger, SlaveMode::Gated) | SMCR_ECE;
+                    self.tim.smcr().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(SMCR_SLAVE_MASK | 0xff00)) | bits)
+                    });
+                    self.tim.cnt().reset();
+                    self.tim.cr1().modify(|_, w| w.cen().set_bit());
+                }
+
+                fn pulses(&self) -> u32 {
+                    self.tim.cnt().read().bits()
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! gate_window {
+    ($($TIM:ident,)+) => {
+        $(
+            impl GateWindow for Timer<$TIM> {
+                fn start_window(&mut self, window: MicroSecond) -> u64 {
+                    let cycles = (self.clk.raw() as u64 * window.ticks() as u64 / 1_000_000).max(2);
+                    let psc = ((cycles - 1) / 0x1_0000).min(0xffff);
+                    let arr = (cycles / (psc + 1)).clamp(2, 0x1_0000) - 1;
+
+                    self.tim.cr1().modify(|_, w| w.cen().clear_bit().urs().set_bit());
+                    self.tim.cr2().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0b111 << 4)) | ((MasterMode::Enable as u32) << 4))
+                    });
+                    self.tim.psc().write(|w| unsafe { w.bits(psc as u32) });
+                    self.tim.arr().write(|w| unsafe { w.bits(arr as u32) });
+                    self.tim.egr().write(|w| w.ug().set_bit());
+                    self.tim.cr1().modify(|_, w| w.opm().set_bit().cen().set_bit());
+                    (psc + 1) * (arr + 1)
+                }
+
+                fn window_open(&self) -> bool {
+                    self.tim.cr1().read().cen().bit_is_set()
+                }
+
+                fn clock(&self) -> Hertz {
+                    self.clk
+                }
+            }
+        )+
+    }
+}
+
+pulse_counter! {
+    TIM1: 0xffff,
+    TIM3: 0xffff,
+}
+
+#[cfg(feature = "stm32g0x1")]
+pulse_counter! {
+    TIM2: 0xffff_ffff_u32,
+}
+
+gate_window! {
+    TIM1,
+    TIM3,
+}
+
+#[cfg(feature = "stm32g0x1")]
+gate_window! {
+    TIM2,
+}
+
+#[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081"))]
+gate_window! {
+    TIM6,
+    TIM7,
+    TIM15,
+}
@@ -5,13 +5,21 @@ use crate::time::{Hertz, MicroSecond};
 use core::marker::PhantomData;
 use cortex_m::peripheral::syst::SystClkSource;
 use cortex_m::peripheral::SYST;
+use hal::delay::DelayNs;
 use void::Void;
 
+pub mod capture;
+pub mod debounce;
 pub mod delay;
+pub mod dither;
+pub mod fan;
+pub mod frequency;
 pub mod opm;
 pub mod pins;
 pub mod pwm;
 pub mod qei;
+pub mod servo;
+pub mod soft;
 pub mod stopwatch;
 
 /// Hardware timers
@@ -20,25 +28,150 @@ pub struct Timer<TIM> {
     tim: TIM,
 }
 
-pub struct Channel<const N: usize>;
+/// Timer channel marker (type state)
+pub struct Ch<const N: usize>;
 
-impl<const N: usize> Channel<N> {
+impl<const N: usize> Ch<N> {
     const N: usize = N;
+
+    /// Runtime representation of this channel
+    pub const CHANNEL: Channel = match N {
+        0 => Channel::Ch1,
+        1 => Channel::Ch2,
+        2 => Channel::Ch3,
+        3 => Channel::Ch4,
+        _ => panic!("timer channel out of range"),
+    };
 }
 
-type Channel1 = Channel<0>;
-type Channel2 = Channel<1>;
-type Channel3 = Channel<2>;
-type Channel4 = Channel<3>;
+pub type Channel1 = Ch<0>;
+pub type Channel2 = Ch<1>;
+pub type Channel3 = Ch<2>;
+pub type Channel4 = Ch<3>;
+
+/// Timer channel
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Ch1 = 0,
+    Ch2 = 1,
+    Ch3 = 2,
+    Ch4 = 3,
+}
+
+impl Channel {
+    /// Zero-based channel index
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl<const N: usize> From<Ch<N>> for Channel {
+    fn from(_: Ch<N>) -> Self {
+        Ch::<N>::CHANNEL
+    }
+}
+
+/// Timer configuration error
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimerError {
+    /// The timeout is shorter than two timer clock cycles
+    TimeoutTooShort,
+    /// The timeout does not fit the prescaler and auto-reload registers
+    TimeoutTooLong,
+}
+
+/// Master mode selection, i.e. the event driving the TRGO output
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MasterMode {
+    /// UG bit or slave mode reset
+    Reset = 0b000,
+    /// Counter enable
+    Enable = 0b001,
+    /// Update event
+    Update = 0b010,
+    /// Pulse on every channel 1 capture or compare match
+    ComparePulse = 0b011,
+    /// OC1REF signal
+    Compare1 = 0b100,
+    /// OC2REF signal
+    Compare2 = 0b101,
+    /// OC3REF signal
+    Compare3 = 0b110,
+    /// OC4REF signal
+    Compare4 = 0b111,
+}
+
+/// Slave mode trigger input selection
+///
+/// The timers connected to the internal triggers ITR0 – ITR3 are listed in
+/// the reference manual, section "TIMx internal trigger connection".
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TriggerSource {
+    Itr0 = 0b000,
+    Itr1 = 0b001,
+    Itr2 = 0b010,
+    Itr3 = 0b011,
+    /// TI1 edge detector
+    Ti1FEd = 0b100,
+    /// Filtered timer input 1
+    Ti1Fp1 = 0b101,
+    /// Filtered timer input 2
+    Ti2Fp2 = 0b110,
+    /// External trigger input
+    Etrf = 0b111,
+}
+
+/// Slave mode selection
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlaveMode {
+    /// Slave mode disabled, the counter is clocked by the internal clock
+    Disabled = 0b0000,
+    /// The trigger reinitializes the counter
+    Reset = 0b0100,
+    /// The counter runs while the trigger input is high
+    Gated = 0b0101,
+    /// The trigger starts the counter
+    Trigger = 0b0110,
+    /// The trigger input clocks the counter
+    ExternalClock1 = 0b0111,
+    /// The trigger reinitializes and starts the counter
+    ResetTrigger = 0b1000,
+}
+
+/// Active level of a gate or trigger input
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// SMS and TS fields of SMCR, including the split SMS\[3\] and TS\[4:3\] bits
+const SMCR_SLAVE_MASK: u32 = 0b111 | (0b111 << 4) | (1 << 16) | (0b11 << 20);
+
+/// TS field of SMCR, including the split TS\[4:3\] bits
+const SMCR_TS_MASK: u32 = (0b111 << 4) | (0b11 << 20);
+
+/// Encodes slave mode and trigger selection into SMCR bits
+fn smcr_slave_bits(source: TriggerSource, mode: SlaveMode) -> u32 {
+    let sms = mode as u32;
+    let ts = source as u32;
+    (sms & 0b111) | ((sms >> 3) << 16) | ((ts & 0b111) << 4) | ((ts >> 3) << 20)
+}
 
 /// System timer
 impl Timer<SYST> {
-    /// Configures the SYST clock as a periodic count down timer
+    /// Configures the SYST clock as a periodic count down timer clocked by HCLK
     pub fn syst(mut syst: SYST, rcc: &mut Rcc) -> Self {
         syst.set_clock_source(SystClkSource::Core);
         Timer {
             tim: syst,
-            clk: rcc.clocks.apb_tim_clk,
+            clk: rcc.clocks.ahb_clk,
         }
     }
 
@@ -52,9 +185,23 @@ impl Timer<SYST> {
         self.tim.disable_interrupt()
     }
 
+    /// Gets the SysTick current value. SysTick counts down from the reload
+    /// value, so elapsed cycles are `start.wrapping_sub(end) & 0x00ff_ffff`
     pub fn get_current(&self) -> u32 {
         SYST::get_current()
     }
+
+    /// Gets the SysTick reload value
+    pub fn reload_value(&self) -> u32 {
+        SYST::get_reload()
+    }
+
+    /// Stops the counter and its interrupt and releases the SYST peripheral
+    pub fn release(mut self) -> SYST {
+        self.tim.disable_counter();
+        self.tim.disable_interrupt();
+        self.tim
+    }
 }
 
 impl Timer<SYST> {
@@ -73,6 +220,27 @@ impl Timer<SYST> {
             Err(nb::Error::WouldBlock)
         }
     }
+
+    /// Returns the time left until the counter wraps
+    pub fn remaining(&self) -> MicroSecond {
+        let us = SYST::get_current() as u64 * 1_000_000 / self.clk.raw() as u64;
+        MicroSecond::from_ticks(us as u32)
+    }
+
+    /// Blocks for `timeout`, looping over the 24 bit reload for long timeouts
+    pub fn delay(&mut self, timeout: impl Into<MicroSecond>) {
+        self.delay_us(timeout.into().ticks());
+    }
+}
+
+/// Blocking delay on the SysTick, looping over the 24 bit reload for long
+/// delays. This replaces any timeout started with `start` and leaves the
+/// counter stopped.
+impl DelayNs for Timer<SYST> {
+    fn delay_ns(&mut self, ns: u32) {
+        let cycles = (self.clk.raw() as u64 * ns as u64).div_ceil(1_000_000_000);
+        delay::syst_delay(&mut self.tim, cycles);
+    }
 }
 
 pub trait TimerExt<TIM> {
@@ -100,6 +268,26 @@ macro_rules! timers {
                     }
                 }
 
+                /// Wraps a TIM peripheral that is already enabled and configured,
+                /// e.g. by a bootloader or another RTIC task, without resetting it
+                pub fn from_configured(tim: $TIM, rcc: &Rcc) -> Self {
+                    Timer {
+                        tim,
+                        clk: rcc.clocks.apb_tim_clk,
+                    }
+                }
+
+                /// Re-acquires the timer after `release`, e.g. in a panic
+                /// handler or after a bootloader handoff, without resetting it
+                ///
+                /// # Safety
+                ///
+                /// Advanced use only: no other owner of the peripheral may
+                /// access it while the returned `Timer` is in use.
+                pub unsafe fn steal(rcc: &Rcc) -> Self {
+                    Self::from_configured($TIM::steal(), rcc)
+                }
+
                 /// Pauses timer
                 pub fn pause(&mut self) {
                     self.tim.cr1().modify(|_, w| w.cen().clear_bit());
@@ -125,6 +313,12 @@ macro_rules! timers {
                     self.tim.sr().modify(|_, w| w.uif().clear_bit());
                 }
 
+                /// Generates an update event, reloading the prescaler and auto-reload
+                /// shadow registers immediately and resetting the counter
+                pub fn generate_update(&mut self) {
+                    self.tim.egr().write(|w| w.ug().set_bit());
+                }
+
                 /// Resets counter value
                 pub fn reset(&mut self) {
                     self.tim.cnt().reset();
@@ -132,14 +326,44 @@ macro_rules! timers {
 
                 /// Gets timer counter current value
                 pub fn get_current(&self) -> u32 {
+                    self.counter()
+                }
+
+                /// Gets timer counter current value.
+                ///
+                /// Both counter halves are taken from a single CNT register
+                /// read, so the value cannot tear on a low half rollover, also
+                /// when the main loop and an interrupt both read it.
+                pub fn counter(&self) -> u32 {
+                    let cnt = self.tim.cnt().read();
                     let _high = 0;
                     $(
-                        let _high = self.tim.cnt().read().$cnt_h().bits() as u32;
+                        let _high = cnt.$cnt_h().bits() as u32;
                     )*
-                    let low = self.tim.cnt().read().$cnt().bits() as u32;
+                    let low = cnt.$cnt().bits() as u32;
                     low | (_high << 16)
                 }
 
+                /// Converts counter ticks at the current prescaler to time
+                pub fn ticks_to_us(&self, ticks: u32) -> MicroSecond {
+                    let psc = self.tim.psc().read().bits() as u64 + 1;
+                    let us = ticks as u64 * psc * 1_000_000 / self.clk.raw() as u64;
+                    MicroSecond::from_ticks(us.min(u32::MAX as u64) as u32)
+                }
+
+                /// Returns the time left until the next update event
+                pub fn remaining(&self) -> MicroSecond {
+                    let arr = self.tim.arr().read().bits();
+                    self.ticks_to_us(arr.saturating_sub(self.counter()))
+                }
+
+                /// Converts time to counter ticks at the current prescaler
+                pub fn us_to_ticks(&self, time: MicroSecond) -> u32 {
+                    let psc = self.tim.psc().read().bits() as u64 + 1;
+                    let ticks = time.ticks() as u64 * self.clk.raw() as u64 / (psc * 1_000_000);
+                    ticks.min(u32::MAX as u64) as u32
+                }
+
                 pub fn start(&mut self, timeout: MicroSecond) {
                     // Pause the counter. Also set URS so that when we set UG below, it will
                     // generate an update event *without* triggering an interrupt.
@@ -173,6 +397,35 @@ macro_rules! timers {
                     }
                 }
 
+                /// Waits for an update event without clearing the update interrupt flag
+                ///
+                /// Unlike [`Self::wait()`], UIF is left set once this returns `Ok`, so it keeps
+                /// returning `Ok` until the flag is cleared with [`Self::clear_irq()`]. Use
+                /// this when the update interrupt is also listened to, and clear the flag in
+                /// exactly one place (usually the ISR) so no period is lost to a race between
+                /// the ISR and the polling code.
+                pub fn wait_no_clear(&self) -> nb::Result<(), Void> {
+                    if self.tim.sr().read().uif().bit_is_clear() {
+                        Err(nb::Error::WouldBlock)
+                    } else {
+                        Ok(())
+                    }
+                }
+
+                /// Starts the timer and blocks until `timeout` expired, looping in
+                /// steps of at most one second for long timeouts. The timer is
+                /// paused afterwards.
+                pub fn delay(&mut self, timeout: impl Into<MicroSecond>) {
+                    let mut remaining = timeout.into().ticks();
+                    while remaining > 0 {
+                        let step = remaining.min(1_000_000);
+                        remaining -= step;
+                        self.start(MicroSecond::from_ticks(step));
+                        let _ = nb::block!(self.wait());
+                    }
+                    self.pause();
+                }
+
                 /// Releases the TIM peripheral
                 pub fn release(self) -> $TIM {
                     self.tim
@@ -188,6 +441,199 @@ macro_rules! timers {
     }
 }
 
+macro_rules! timers_compare {
+    ($($TIM:ident: $channels:expr,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Starts listening for compare events on the given channel
+                pub fn listen_compare(&mut self, channel: Channel) {
+                    assert!(channel.index() < $channels);
+                    self.tim.dier().modify(|_, w| w.ccie(channel as u8).set_bit());
+                }
+
+                /// Stops listening for compare events on the given channel
+                pub fn unlisten_compare(&mut self, channel: Channel) {
+                    assert!(channel.index() < $channels);
+                    self.tim.dier().modify(|_, w| w.ccie(channel as u8).clear_bit());
+                }
+
+                /// Returns true if a compare event is pending on the given channel
+                pub fn is_compare_pending(&self, channel: Channel) -> bool {
+                    assert!(channel.index() < $channels);
+                    self.tim.sr().read().ccif(channel as u8).bit_is_set()
+                }
+
+                /// Clears compare interrupt flag of the given channel
+                pub fn clear_compare_irq(&mut self, channel: Channel) {
+                    assert!(channel.index() < $channels);
+                    self.tim.sr().modify(|_, w| w.ccif(channel as u8).clear_bit());
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! timers_checked {
+    ($($TIM:ident: $max_arr:expr,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Starts the timer like `start`, but returns an error instead of
+                /// silently programming a wrong period when `timeout` does not
+                /// fit the 16-bit prescaler and the auto-reload register.
+                pub fn try_start(&mut self, timeout: MicroSecond) -> Result<(), TimerError> {
+                    let cycles = self.clk.raw() as u64 * timeout.ticks() as u64 / 1_000_000;
+                    if cycles < 2 {
+                        return Err(TimerError::TimeoutTooShort);
+                    }
+                    let psc = (cycles - 1) / ($max_arr as u64 + 1);
+                    if psc > 0xffff {
+                        return Err(TimerError::TimeoutTooLong);
+                    }
+                    let arr = cycles / (psc + 1) - 1;
+
+                    self.tim.cr1().modify(|_, w| w.cen().clear_bit().urs().set_bit());
+                    self.tim.cnt().reset();
+                    self.tim.sr().modify(|_, w| w.uif().clear_bit());
+
+                    self.tim.psc().write(|w| w.psc().set(psc as u16));
+                    self.tim.arr().write(|w| unsafe { w.bits(arr as u32) });
+                    self.tim.egr().write(|w| w.ug().set_bit());
+
+                    self.tim.cr1().modify(|_, w| w.cen().set_bit());
+                    Ok(())
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! timers_slave {
+    ($($TIM:ident: $max_arr:expr,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Selects the slave `mode` and its trigger input `source`
+                pub fn set_slave_mode(&mut self, source: TriggerSource, mode: SlaveMode) {
+                    let bits = smcr_slave_bits(source, mode);
+                    self.tim.smcr().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !SMCR_SLAVE_MASK) | bits)
+                    });
+                }
+
+                /// Selects the event driving the TRGO output of this timer
+                pub fn set_master_mode(&mut self, mode: MasterMode) {
+                    self.tim.cr2().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0b111 << 4)) | ((mode as u32) << 4))
+                    });
+                }
+
+                /// Starts the counter and, on the same cycle, every stopped
+                /// slave timer set to `SlaveMode::Trigger` on the internal
+                /// trigger connected to the TRGO of this timer:
+                ///
+                /// ```ignore
+                /// tim3.start(1.millis());
+                /// tim3.pause();
+                /// tim3.set_slave_mode(TriggerSource::Itr0, SlaveMode::Trigger);
+                /// tim1.start_synchronized();
+                /// ```
+                pub fn start_synchronized(&mut self) {
+                    self.set_master_mode(MasterMode::Enable);
+                    self.tim.cr1().modify(|_, w| w.cen().set_bit());
+                }
+
+                /// Restarts the counter from 0 in gated mode: it only counts
+                /// while `source` is at its active level. After the gate
+                /// closes `counter()` holds the active time in timer ticks, at
+                /// the timer clock divided by the current prescaler.
+                ///
+                /// `polarity` applies to `Ti1Fp1`, `Ti2Fp2` (which configure
+                /// channel 1 or 2 as input) and `Etrf`. ARR is set to the
+                /// maximum so the counter does not wrap while the gate is open.
+                ///
+                /// # Panics
+                ///
+                /// Panics for `TriggerSource::Ti1FEd`, which cannot gate the
+                /// counter.
+                pub fn gated_by(&mut self, source: TriggerSource, polarity: Polarity) {
+                    assert!(source != TriggerSource::Ti1FEd);
+                    let low = polarity == Polarity::ActiveLow;
+                    self.tim.cr1().modify(|_, w| w.cen().clear_bit());
+                    match source {
+                        TriggerSource::Ti1Fp1 => {
+                            // CC1S = 01, CC1P selects the polarity, CC1NP cleared
+                            self.tim.ccmr1_output().modify(|r, w| unsafe {
+                                w.bits((r.bits() & !0b11) | 0b01)
+                            });
+                            self.tim.ccer().modify(|r, w| unsafe {
+                                w.bits((r.bits() & !0b1010) | ((low as u32) << 1))
+                            });
+                        }
+                        TriggerSource::Ti2Fp2 => {
+                            // CC2S = 01, CC2P selects the polarity, CC2NP cleared
+                            self.tim.ccmr1_output().modify(|r, w| unsafe {
+                                w.bits((r.bits() & !(0b11 << 8)) | (0b01 << 8))
+                            });
+                            self.tim.ccer().modify(|r, w| unsafe {
+                                w.bits((r.bits() & !(0b1010 << 4)) | ((low as u32) << 5))
+                            });
+                        }
+                        TriggerSource::Etrf => {
+                            // ETP
+                            self.tim.smcr().modify(|r, w| unsafe {
+                                w.bits((r.bits() & !(1 << 15)) | ((low as u32) << 15))
+                            });
+                        }
+                        _ => {}
+                    }
+                    self.set_slave_mode(source, SlaveMode::Gated);
+                    self.tim.arr().write(|w| unsafe { w.bits($max_arr) });
+                    self.tim.cnt().reset();
+                    self.tim.cr1().modify(|_, w| w.cen().set_bit());
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! timers_capture {
+    ($($TIM:ident,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Restarts the counter for input capture on TI1: every
+                /// rising TI1 edge resets the counter. `ccmr1` and `ccer`
+                /// configure the capture channels 1 and 2. Only counter
+                /// overflows set UIF.
+                fn start_reset_capture(&mut self, psc: u32, arr: u32, ccmr1: u32, ccer: u32) {
+                    self.tim.cr1().modify(|_, w| w.cen().clear_bit());
+                    self.tim.psc().write(|w| unsafe { w.bits(psc) });
+                    self.tim.arr().write(|w| unsafe { w.bits(arr) });
+                    self.tim.ccmr1_input().write(|w| unsafe { w.bits(ccmr1) });
+                    self.tim.ccer().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !0xff) | ccer)
+                    });
+                    self.set_slave_mode(TriggerSource::Ti1Fp1, SlaveMode::Reset);
+                    self.tim.egr().write(|w| w.ug().set_bit());
+                    self.tim.sr().reset();
+                    self.tim.cr1().modify(|_, w| w.urs().set_bit().cen().set_bit());
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! timers_debug {
+    ($($TIM:ident: ($apb2:expr, $bit:expr),)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Stops the counter while the core is halted by a debugger
+                pub fn freeze_on_debug(&mut self, rcc: &mut Rcc, freeze: bool) {
+                    rcc.freeze_on_debug($apb2, $bit, freeze);
+                }
+            }
+        )+
+    }
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ExternalClockMode {
@@ -273,3 +719,111 @@ timers! {
     TIM7: (tim7, cnt),
     TIM15: (tim15, cnt),
 }
+
+timers_compare! {
+    TIM1: 4,
+    TIM3: 4,
+    TIM14: 1,
+    TIM16: 1,
+    TIM17: 1,
+}
+
+#[cfg(feature = "stm32g0x1")]
+timers_compare! {
+    TIM2: 4,
+}
+
+#[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081"))]
+timers_compare! {
+    TIM15: 2,
+}
+
+timers_checked! {
+    TIM1: 0xffff,
+    TIM3: 0xffff,
+    TIM14: 0xffff,
+    TIM16: 0xffff,
+    TIM17: 0xffff,
+}
+
+#[cfg(feature = "stm32g0x1")]
+timers_checked! {
+    TIM2: 0xffff_ffff_u32,
+}
+
+#[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081"))]
+timers_checked! {
+    TIM6: 0xffff,
+    TIM7: 0xffff,
+    TIM15: 0xffff,
+}
+
+timers_slave! {
+    TIM1: 0xffff,
+    TIM3: 0xffff,
+}
+
+#[cfg(feature = "stm32g0x1")]
+timers_slave! {
+    TIM2: 0xffff_ffff_u32,
+}
+
+#[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081"))]
+timers_slave! {
+    TIM15: 0xffff,
+}
+
+timers_capture! {
+    TIM1,
+    TIM3,
+}
+
+#[cfg(feature = "stm32g0x1")]
+timers_capture! {
+    TIM2,
+}
+
+macro_rules! timers_updown {
+    ($($TIM:ident,)+) => {
+        $(
+            impl Timer<$TIM> {
+                /// Returns true while the counter counts down (CR1 DIR). In
+                /// center-aligned mode the update event, see `listen`, marks
+                /// every change of direction.
+                pub fn counting_down(&self) -> bool {
+                    self.tim.cr1().read().dir().bit_is_set()
+                }
+            }
+        )+
+    }
+}
+
+timers_updown! {
+    TIM1,
+    TIM3,
+}
+
+#[cfg(feature = "stm32g0x1")]
+timers_updown! {
+    TIM2,
+}
+
+timers_debug! {
+    TIM1: (true, 11),
+    TIM3: (false, 1),
+    TIM14: (true, 15),
+    TIM16: (true, 17),
+    TIM17: (true, 18),
+}
+
+#[cfg(feature = "stm32g0x1")]
+timers_debug! {
+    TIM2: (false, 0),
+}
+
+#[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081"))]
+timers_debug! {
+    TIM6: (false, 4),
+    TIM7: (false, 5),
+    TIM15: (true, 16),
+}
@@ -83,9 +83,63 @@ macro_rules! opm {
     }
 }
 
+macro_rules! opm_retrigger {
+    ($($TIMX:ident,)+) => {
+        $(
+            impl Opm<$TIMX> {
+                /// Selects the trigger input starting the pulse in retriggerable mode
+                pub fn set_trigger(&mut self, source: TriggerSource) {
+                    let tim = unsafe { &*$TIMX::ptr() };
+                    let bits = smcr_slave_bits(source, SlaveMode::Disabled);
+                    tim.smcr().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !SMCR_TS_MASK) | bits)
+                    });
+                }
+
+                /// Enables retriggerable one-pulse mode: every trigger edge (re)starts
+                /// the pulse, so the output only returns inactive once no trigger was
+                /// seen for a full pulse period.
+                ///
+                /// Uses the combined reset + trigger slave mode and switches the
+                /// enabled channels to retriggerable OPM mode 1. Set the channel
+                /// delays to 0 for the pulse to start at the trigger.
+                pub fn retriggerable(&mut self, enable: bool) {
+                    let tim = unsafe { &*$TIMX::ptr() };
+                    let mode = if enable { SlaveMode::ResetTrigger } else { SlaveMode::Disabled };
+                    tim.smcr().modify(|r, w| unsafe {
+                        let ts = r.bits() & SMCR_TS_MASK;
+                        let sms = smcr_slave_bits(TriggerSource::Itr0, mode);
+                        w.bits((r.bits() & !SMCR_SLAVE_MASK) | ts | sms)
+                    });
+                    tim.ccmr1_output().modify(|r, w| unsafe { w.bits(retrigger_ccmr(r.bits(), enable)) });
+                    tim.ccmr2_output().modify(|r, w| unsafe { w.bits(retrigger_ccmr(r.bits(), enable)) });
+                    tim.cr1().modify(|_, w| w.opm().set_bit());
+                }
+            }
+        )+
+    }
+}
+
+/// Switches the output channels of a CCMR register value between PWM mode 2
+/// (0b0111) and retriggerable OPM mode 1 (0b1000)
+fn retrigger_ccmr(mut bits: u32, enable: bool) -> u32 {
+    // OCxM[2:0] and OCxM[3] offsets of both channels of the register
+    for (low, high) in [(4, 16), (12, 24)] {
+        let mode = ((bits >> low) & 0b111) | (((bits >> high) & 1) << 3);
+        let new = match (mode, enable) {
+            (0b0111, true) => 0b1000,
+            (0b1000, false) => 0b0111,
+            _ => continue,
+        };
+        bits &= !((0b111 << low) | (1 << high));
+        bits |= ((new & 0b111) << low) | ((new >> 3) << high);
+    }
+    bits
+}
+
 macro_rules! opm_hal {
     ($($TIMX:ident:
-        ($CH:ty, $ccxe:ident, $ccmrx_output:ident, $ocxm:ident, $ocxfe:ident, $ccrx:ident),)+
+        ($CH:ty, $ccxe:ident, $ccmrx_output:ident, $ocxm:ident, $ocxm_3:ident, $ocxfe:ident, $ccrx:ident),)+
     ) => {
         $(
             impl OpmPin<$TIMX, $CH> {
@@ -113,7 +167,11 @@ macro_rules! opm_hal {
                     unsafe {
                         let tim = &*$TIMX::ptr();
                         tim.$ccrx().write(|w| w.bits(self.delay));
-                        tim.$ccmrx_output().modify(|_, w| w.$ocxm().bits(7).$ocxfe().set_bit());
+                        // Keep retriggerable OPM mode 1 (0b1000) if selected, PWM mode 2 otherwise
+                        tim.$ccmrx_output().modify(|r, w| {
+                            let mode = if r.$ocxm_3().bit_is_set() { 0 } else { 7 };
+                            w.$ocxm().bits(mode).$ocxfe().set_bit()
+                        });
                     }
                 }
             }
@@ -122,25 +180,25 @@ macro_rules! opm_hal {
 }
 
 opm_hal! {
-    TIM1: (Channel1, cc1e, ccmr1_output, oc1m, oc1fe, ccr1),
-    TIM1: (Channel2, cc2e, ccmr1_output, oc2m, oc2fe, ccr2),
-    TIM1: (Channel3, cc3e, ccmr2_output, oc3m, oc3fe, ccr3),
-    TIM1: (Channel4, cc4e, ccmr2_output, oc4m, oc4fe, ccr4),
-    TIM3: (Channel1, cc1e, ccmr1_output, oc1m, oc1fe, ccr1),
-    TIM3: (Channel2, cc2e, ccmr1_output, oc2m, oc2fe, ccr2),
-    TIM3: (Channel3, cc3e, ccmr2_output, oc3m, oc3fe, ccr3),
-    TIM3: (Channel4, cc4e, ccmr2_output, oc4m, oc4fe, ccr4),
-    TIM14: (Channel1, cc1e, ccmr1_output, oc1m, oc1fe, ccr1),
-    TIM16: (Channel1, cc1e, ccmr1_output, oc1m, oc1fe, ccr1),
-    TIM17: (Channel1, cc1e, ccmr1_output, oc1m, oc1fe, ccr1),
+    TIM1: (Channel1, cc1e, ccmr1_output, oc1m, oc1m_3, oc1fe, ccr1),
+    TIM1: (Channel2, cc2e, ccmr1_output, oc2m, oc2m_3, oc2fe, ccr2),
+    TIM1: (Channel3, cc3e, ccmr2_output, oc3m, oc3m_3, oc3fe, ccr3),
+    TIM1: (Channel4, cc4e, ccmr2_output, oc4m, oc4m_3, oc4fe, ccr4),
+    TIM3: (Channel1, cc1e, ccmr1_output, oc1m, oc1m_3, oc1fe, ccr1),
+    TIM3: (Channel2, cc2e, ccmr1_output, oc2m, oc2m_3, oc2fe, ccr2),
+    TIM3: (Channel3, cc3e, ccmr2_output, oc3m, oc3m_3, oc3fe, ccr3),
+    TIM3: (Channel4, cc4e, ccmr2_output, oc4m, oc4m_3, oc4fe, ccr4),
+    TIM14: (Channel1, cc1e, ccmr1_output, oc1m, oc1m_3, oc1fe, ccr1),
+    TIM16: (Channel1, cc1e, ccmr1_output, oc1m, oc1m_3, oc1fe, ccr1),
+    TIM17: (Channel1, cc1e, ccmr1_output, oc1m, oc1m_3, oc1fe, ccr1),
 }
 
 #[cfg(feature = "stm32g0x1")]
 opm_hal! {
-    TIM2: (Channel1, cc1e, ccmr1_output, oc1m, oc1fe, ccr1),
-    TIM2: (Channel2, cc2e, ccmr1_output, oc2m, oc2fe, ccr2),
-    TIM2: (Channel3, cc3e, ccmr2_output, oc3m, oc3fe, ccr3),
-    TIM2: (Channel4, cc4e, ccmr2_output, oc4m, oc4fe, ccr4),
+    TIM2: (Channel1, cc1e, ccmr1_output, oc1m, oc1m_3, oc1fe, ccr1),
+    TIM2: (Channel2, cc2e, ccmr1_output, oc2m, oc2m_3, oc2fe, ccr2),
+    TIM2: (Channel3, cc3e, ccmr2_output, oc3m, oc3m_3, oc3fe, ccr3),
+    TIM2: (Channel4, cc4e, ccmr2_output, oc4m, oc4m_3, oc4fe, ccr4),
 }
 
 opm! {
@@ -160,3 +218,13 @@ opm! {
 opm! {
     TIM15: (tim15, arr),
 }
+
+opm_retrigger! {
+    TIM1,
+    TIM3,
+}
+
+#[cfg(feature = "stm32g0x1")]
+opm_retrigger! {
+    TIM2,
+}
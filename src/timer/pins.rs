@@ -3,7 +3,7 @@ use crate::gpio::{AltFunction, DefaultMode};
 use crate::stm32::*;
 use crate::timer::*;
 
-pub trait TimerPin<TIM> {
+pub trait TimerPin<TIM>: crate::Sealed {
     type Channel;
 
     fn setup(&self);
@@ -58,6 +58,70 @@ macro_rules! timer_pins {
     };
 }
 
+/// External trigger (ETR) input pin of `TIM`
+pub trait EtrPin<TIM>: crate::Sealed {
+    fn setup(&self);
+}
+
+macro_rules! etr_pins {
+    ($TIMX:ident, [ $(($pin:ty, $af_mode:expr),)+ ]) => {
+        $(
+            impl EtrPin<$TIMX> for $pin {
+                fn setup(&self) {
+                    self.set_alt_mode($af_mode);
+                }
+            }
+        )+
+    };
+}
+
+etr_pins!(TIM1, [
+    (PA12<DefaultMode>, AltFunction::AF2),
+]);
+
+#[cfg(feature = "stm32g0x1")]
+etr_pins!(TIM2, [
+    (PA0<DefaultMode>, AltFunction::AF2),
+    (PA5<DefaultMode>, AltFunction::AF2),
+    (PA15<DefaultMode>, AltFunction::AF2),
+]);
+
+etr_pins!(TIM3, [
+    (PD2<DefaultMode>, AltFunction::AF1),
+]);
+
+/// Break input (BKIN) pin of `TIM`
+pub trait BreakPin<TIM>: crate::Sealed {
+    fn setup(&self);
+}
+
+macro_rules! break_pins {
+    ($TIMX:ident, [ $(($pin:ty, $af_mode:expr),)+ ]) => {
+        $(
+            impl BreakPin<$TIMX> for $pin {
+                fn setup(&self) {
+                    self.set_alt_mode($af_mode);
+                }
+            }
+        )+
+    };
+}
+
+break_pins!(TIM1, [
+    (PA6<DefaultMode>, AltFunction::AF2),
+    (PB12<DefaultMode>, AltFunction::AF2),
+    (PC13<DefaultMode>, AltFunction::AF2),
+]);
+
+break_pins!(TIM16, [
+    (PB5<DefaultMode>, AltFunction::AF2),
+]);
+
+break_pins!(TIM17, [
+    (PA10<DefaultMode>, AltFunction::AF5),
+    (PB4<DefaultMode>, AltFunction::AF5),
+]);
+
 macro_rules! trigger_pins {
     ($TIMX:ident, [ $(($pin:ty, $ccp:ident $(,$icf:ident)*),)+ ]) => {
         $(
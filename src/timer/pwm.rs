@@ -1,10 +1,17 @@
 //! # Pulse Width Modulation
+//!
+//! All channels of a timer share one counter, so in edge-aligned mode every
+//! channel output starts its pulse at the same time. To phase shift outputs,
+//! either use a slave timer reset by the TRGO of a master timer (see
+//! `Pwm::set_master_mode` and `Pwm::sync_to`) or preload the counter with
+//! `Pwm::set_counter` before starting related timers.
 use core::marker::PhantomData;
 
 use crate::rcc::*;
 use crate::stm32::*;
-use crate::time::Hertz;
-use crate::timer::pins::TimerPin;
+use crate::time::{Hertz, MicroSecond};
+use crate::timer::pins::{BreakPin, TimerPin};
+use crate::timer::servo::{Servo, SERVO_FREQ};
 use crate::timer::*;
 use embedded_hal::pwm::{ErrorKind, ErrorType, SetDutyCycle};
 
@@ -70,6 +77,23 @@ impl<TIM> Pwm<TIM> {
     }
 }
 
+impl<TIM, CH> PwmPin<TIM, CH> {
+    /// Routes the channel output to the given pin
+    pub fn connect<PIN>(&mut self, pin: PIN)
+    where
+        PIN: TimerPin<TIM, Channel = CH>,
+    {
+        pin.setup();
+    }
+}
+
+impl<TIM, const N: usize> PwmPin<TIM, Ch<N>> {
+    /// Returns the timer channel this pin is bound to
+    pub fn channel(&self) -> Channel {
+        Ch::<N>::CHANNEL
+    }
+}
+
 macro_rules! pwm {
     ($($TIMX:ident: ($timX:ident, $arr:ident $(,$arr_h:ident)*),)+) => {
         $(
@@ -111,7 +135,9 @@ macro_rules! pwm {
                     let psc = (ratio - 1) / 0xffff;
 
                     unsafe {
-                        let arr = ratio / (psc + 1) - 1;
+                        // ARR stays below 0xffff, so the 100% compare value
+                        // ARR + 1 of `get_max_duty` fits the 16 bit CCRs
+                        let arr = (ratio / (psc + 1) - 1).min(0xfffe);
                         self.tim.psc().write(|w| w.psc().bits(psc as u16));
                         self.tim.arr().write(|w| w.$arr().bits((arr as u16).into()));
                         $(
@@ -145,6 +171,154 @@ macro_rules! pwm {
                         / (self.tim.psc().read().bits() + 1)
                         / (self.tim.arr().read().bits() + 1))
                 }
+
+                /// Sets the timer to the servo refresh rate and wraps the
+                /// channel into a `Servo`. The channel still has to be enabled.
+                pub fn servo<CH>(&mut self, pin: PwmPin<$TIMX, CH>) -> Servo<PwmPin<$TIMX, CH>>
+                where
+                    PwmPin<$TIMX, CH>: SetDutyCycle,
+                {
+                    self.set_freq(SERVO_FREQ);
+                    Servo::new(pin)
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! pwm_channels {
+    ($($TIMX:ident: ($($CH:ty),+),)+) => {
+        $(
+            impl Pwm<$TIMX> {
+                /// Splits the timer into independent channel handles. The
+                /// timer keeps running at the frequency set before the split.
+                ///
+                /// Channel outputs are not routed to any pin, use
+                /// `PwmPin::connect` to configure the pins.
+                pub fn channels(self) -> ($(PwmPin<$TIMX, $CH>,)+) {
+                    ($(
+                        PwmPin::<$TIMX, $CH> {
+                            tim: PhantomData,
+                            channel: PhantomData,
+                        },
+                    )+)
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! pwm_sync {
+    ($($TIMX:ident,)+) => {
+        $(
+            impl Pwm<$TIMX> {
+                /// Selects the event driving the TRGO output of this timer
+                ///
+                /// With `MasterMode::ComparePulse` a slave timer synced with
+                /// `sync_to` restarts whenever this timer reaches CCR1, so CCR1
+                /// sets the phase offset between both timers.
+                pub fn set_master_mode(&mut self, mode: MasterMode) {
+                    self.tim.cr2().modify(|_, w| unsafe { w.mms().bits(mode as u8) });
+                }
+
+                /// Resets the counter on every edge of the trigger input,
+                /// e.g. the TRGO of a master timer
+                pub fn sync_to(&mut self, trigger: TriggerSource) {
+                    let bits = smcr_slave_bits(trigger, SlaveMode::Reset);
+                    self.tim.smcr().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !SMCR_SLAVE_MASK) | bits)
+                    });
+                }
+
+                /// Sets the counter value, e.g. to apply an initial phase offset
+                pub fn set_counter(&mut self, value: u32) {
+                    self.tim.cnt().write(|w| unsafe { w.bits(value) });
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! pwm_updown {
+    ($($TIMX:ident,)+) => {
+        $(
+            impl Pwm<$TIMX> {
+                /// Returns true while the counter counts down. In
+                /// center-aligned mode the update interrupt fires on every
+                /// change of direction, so the handler can update the
+                /// channels of the coming slope.
+                pub fn counting_down(&self) -> bool {
+                    self.tim.cr1().read().dir().bit_is_set()
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! pwm_idle_state {
+    ($($TIMX:ident: ($channels:expr, $complementary:expr),)+) => {
+        $(
+            impl Pwm<$TIMX> {
+                /// Sets the output levels of `channel` while MOE is cleared:
+                /// `high` for OCx and `complementary` for OCxN, if present
+                pub fn set_idle_state(&mut self, channel: Channel, high: bool, complementary: bool) {
+                    let ch = channel.index();
+                    assert!(ch < $channels);
+                    self.tim.cr2().modify(|_, w| {
+                        if ch < $complementary {
+                            w.oisn(ch as u8).bit(complementary);
+                        }
+                        w.ois(ch as u8).bit(high)
+                    });
+                }
+            }
+        )+
+    }
+}
+
+/// State reported by `Pwm::poll_break_recovery`
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BreakState {
+    /// No break occurred since the last poll
+    Clear,
+    /// The break input is still active, the outputs stay disabled
+    Faulted,
+    /// The break input stayed inactive for the debounce time, the outputs
+    /// are enabled again
+    Recovered,
+}
+
+macro_rules! pwm_break {
+    ($($TIMX:ident,)+) => {
+        $(
+            impl Pwm<$TIMX> {
+                /// Enables the break input on `pin`: while it is at its active
+                /// level, MOE is cleared and the outputs go to their idle state
+                pub fn enable_break<P: BreakPin<$TIMX>>(&mut self, pin: P, polarity: Polarity) {
+                    pin.setup();
+                    self.tim.bdtr().modify(|_, w| {
+                        w.bkp().bit(polarity == Polarity::ActiveHigh).bke().set_bit()
+                    });
+                }
+
+                /// Re-enables the outputs after a break once the break input
+                /// stayed inactive for `debounce`. Call this periodically; with
+                /// automatic output enable (AOE) set, the hardware already
+                /// restarts the outputs on the next update event.
+                pub fn poll_break_recovery<D: DelayNs>(&mut self, delay: &mut D, debounce: MicroSecond) -> BreakState {
+                    if self.tim.sr().read().bif().bit_is_clear() {
+                        return BreakState::Clear;
+                    }
+                    // BIF is set again right away while the break input is active
+                    self.tim.sr().modify(|_, w| w.bif().clear_bit());
+                    delay.delay_us(debounce.ticks());
+                    if self.tim.sr().read().bif().bit_is_set() {
+                        return BreakState::Faulted;
+                    }
+                    self.tim.bdtr().modify(|_, w| w.moe().set_bit());
+                    BreakState::Recovered
+                }
             }
         )+
     }
@@ -163,7 +337,6 @@ macro_rules! pwm_q {
     }
 }
 
-#[cfg(any(feature = "stm32g0x1", feature = "stm32g070"))]
 macro_rules! pwm_hal {
     ($($TIMX:ident:
         ($CH:ty, $ccxe:ident, $ccmrx_output:ident, $ocxpe:ident, $ocxm:ident, $ccrx:ident, $ccrx_l:ident, $ccrx_h:ident),)+
@@ -185,16 +358,52 @@ macro_rules! pwm_hal {
                 }
 
 
+                /// Forces the channel output to its active level, ignoring the
+                /// compare value. The counter keeps running.
+                pub fn force_active(&mut self) {
+                    self.set_output_mode(OutputCompareMode::ForceHigh);
+                }
+
+                /// Forces the channel output to its inactive level
+                pub fn force_inactive(&mut self) {
+                    self.set_output_mode(OutputCompareMode::ForceLow);
+                }
+
+                /// Returns a forced channel to PWM mode 1
+                pub fn resume_pwm(&mut self) {
+                    self.set_output_mode(OutputCompareMode::PwmMode1);
+                }
+
+                fn set_output_mode(&mut self, mode: OutputCompareMode) {
+                    unsafe {
+                        (*$TIMX::ptr()).$ccmrx_output().modify(|_, w| w.$ocxm().bits(mode as u8));
+                    }
+                }
+
+                /// Enables or disables CCR preload. With preload enabled a new
+                /// duty cycle takes effect at the next update event.
+                pub fn set_preload(&mut self, enable: bool) {
+                    unsafe {
+                        (*$TIMX::ptr()).$ccmrx_output().modify(|_, w| w.$ocxpe().bit(enable));
+                    }
+                }
+
+                /// Returns the current compare value. With preload enabled this
+                /// is the last value written, even before the update event.
                 pub fn get_duty(&self) -> u32 {
                     unsafe { (*$TIMX::ptr()).$ccrx().read().bits() }
                 }
 
+                /// Returns the compare value of a 100% duty cycle, ARR + 1 as
+                /// the channels run in PWM mode 1. `set_freq` keeps ARR below
+                /// 0xffff, so the value fits the compare register.
                 pub fn get_max_duty(&self) -> u32 {
-                    unsafe { (*$TIMX::ptr()).arr().read().bits() }
-
+                    unsafe { (*$TIMX::ptr()).arr().read().bits() + 1 }
                 }
 
+                /// Sets the compare value, saturating at `get_max_duty()`
                 pub fn set_duty(&mut self, duty: u32) {
+                    let duty = duty.min(self.get_max_duty());
                     unsafe { (*$TIMX::ptr()).$ccrx().write(|w| w.bits(duty)); }
                 }
             }
@@ -203,13 +412,19 @@ macro_rules! pwm_hal {
                 type Error = ErrorKind;
             }
 
+            /// A 32 bit period is scaled down to the 16 bit duty cycle range
             impl SetDutyCycle for PwmPin<$TIMX, $CH> {
                 fn max_duty_cycle(&self) -> u16 {
-                    self.get_max_duty() as u16
+                    self.get_max_duty().min(u16::MAX as u32) as u16
                 }
 
                 fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Self::Error> {
-                    self.set_duty(duty as u32);
+                    let max = self.get_max_duty();
+                    if max > u16::MAX as u32 {
+                        self.set_duty((duty as u64 * max as u64 / u16::MAX as u64) as u32);
+                    } else {
+                        self.set_duty(duty as u32);
+                    }
                     Ok(())
                 }
             }
@@ -250,15 +465,52 @@ macro_rules! pwm_advanced_hal {
                     }
                 }
 
+                /// Forces the channel output to its active level, ignoring the
+                /// compare value. The counter keeps running.
+                pub fn force_active(&mut self) {
+                    self.set_output_mode(OutputCompareMode::ForceHigh);
+                }
+
+                /// Forces the channel output to its inactive level
+                pub fn force_inactive(&mut self) {
+                    self.set_output_mode(OutputCompareMode::ForceLow);
+                }
+
+                /// Returns a forced channel to PWM mode 1
+                pub fn resume_pwm(&mut self) {
+                    self.set_output_mode(OutputCompareMode::PwmMode1);
+                }
+
+                fn set_output_mode(&mut self, mode: OutputCompareMode) {
+                    unsafe {
+                        (*$TIMX::ptr()).$ccmrx_output().modify(|_, w| w.$ocxm().bits(mode as u8));
+                    }
+                }
+
+                /// Enables or disables CCR preload. With preload enabled a new
+                /// duty cycle takes effect at the next update event.
+                pub fn set_preload(&mut self, enable: bool) {
+                    unsafe {
+                        (*$TIMX::ptr()).$ccmrx_output().modify(|_, w| w.$ocxpe().bit(enable));
+                    }
+                }
+
+                /// Returns the current compare value. With preload enabled this
+                /// is the last value written, even before the update event.
                 pub fn get_duty(&self) -> u16 {
                     unsafe { (*$TIMX::ptr()).$ccrx(<$CH>::N).read().$ccrx().bits() }
                 }
 
+                /// Returns the compare value of a 100% duty cycle, ARR + 1 as
+                /// the channels run in PWM mode 1. `set_freq` keeps ARR below
+                /// 0xffff, so the value fits the 16 bit result.
                 pub fn get_max_duty(&self) -> u16 {
-                    unsafe { (*$TIMX::ptr()).arr().read().arr().bits() }
+                    unsafe { (*$TIMX::ptr()).arr().read().arr().bits() + 1 }
                 }
 
+                /// Sets the compare value, saturating at `get_max_duty()`
                 pub fn set_duty(&mut self, duty: u16) {
+                    let duty = duty.min(self.get_max_duty());
                     unsafe { (*$TIMX::ptr()).$ccrx(<$CH>::N).write(|w| w.$ccrx().bits(duty)); }
                 }
             }
@@ -280,10 +532,7 @@ macro_rules! pwm_advanced_hal {
 
             impl PwmPinMode for PwmPin<$TIMX, $CH>{
                 fn set_compare_mode(&mut self, mode: OutputCompareMode) {
-                    unsafe {
-                        let tim = &*$TIMX::ptr();
-                        tim.$ccmrx_output().modify(|_, w| w.$ocxm().bits(mode as u8));
-                    }
+                    self.set_output_mode(mode);
                 }
             }
         )+
@@ -303,6 +552,7 @@ pwm_advanced_hal! {
 #[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081"))]
 pwm_advanced_hal! {
     TIM15: (Channel1, cc1e: cc1ne, ccmr1_output, oc1pe, oc1m, ccr, moe),
+    TIM15: (Channel2, cc2e, ccmr1_output, oc2pe, oc2m, ccr, moe),
 }
 
 #[cfg(feature = "stm32g0x1")]
@@ -317,7 +567,7 @@ pwm_hal! {
     TIM3: (Channel4, cc4e, ccmr2_output, oc4pe, oc4m, ccr4, ccr4_l, ccr4_h),
 }
 
-#[cfg(feature = "stm32g070")]
+#[cfg(feature = "stm32g0x0")]
 pwm_hal! {
     TIM3: (Channel1, cc1e, ccmr1_output, oc1pe, oc1m, ccr1, ccr1_l, ccr1_h),
     TIM3: (Channel2, cc2e, ccmr1_output, oc2pe, oc2m, ccr2, ccr2_l, ccr2_h),
@@ -343,6 +593,66 @@ pwm! {
     TIM15: (tim15, arr),
 }
 
+pwm_channels! {
+    TIM1: (Channel1, Channel2, Channel3, Channel4),
+    TIM3: (Channel1, Channel2, Channel3, Channel4),
+    TIM14: (Channel1),
+    TIM16: (Channel1),
+    TIM17: (Channel1),
+}
+
+#[cfg(feature = "stm32g0x1")]
+pwm_channels! {
+    TIM2: (Channel1, Channel2, Channel3, Channel4),
+}
+
+#[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081"))]
+pwm_channels! {
+    TIM15: (Channel1, Channel2),
+}
+
+pwm_sync! {
+    TIM1,
+    TIM3,
+}
+
+pwm_updown! {
+    TIM1,
+    TIM3,
+}
+
+#[cfg(feature = "stm32g0x1")]
+pwm_updown! {
+    TIM2,
+}
+
+pwm_idle_state! {
+    TIM1: (4, 3),
+    TIM16: (1, 1),
+    TIM17: (1, 1),
+}
+
+#[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081"))]
+pwm_idle_state! {
+    TIM15: (2, 1),
+}
+
+pwm_break! {
+    TIM1,
+    TIM16,
+    TIM17,
+}
+
+#[cfg(feature = "stm32g0x1")]
+pwm_sync! {
+    TIM2,
+}
+
+#[cfg(any(feature = "stm32g070", feature = "stm32g071", feature = "stm32g081"))]
+pwm_sync! {
+    TIM15,
+}
+
 #[cfg(feature = "stm32g0x1")]
 pwm_q! {
     TIM1: tim1,
@@ -6,7 +6,7 @@ use crate::stm32::{TIM1, TIM2, TIM3};
 #[cfg(feature = "stm32g0x0")]
 use crate::stm32::{TIM1, TIM3};
 
-use crate::timer::pins::TimerPin;
+use crate::timer::pins::{EtrPin, TimerPin};
 use crate::timer::*;
 
 /// Counting direction
@@ -101,6 +101,35 @@ macro_rules! qei {
                     self.tim.cnt().read().$cnt().bits() as u16
                 }
 
+                /// Uses the index pulse on the ETR `pin` to home the counter.
+                ///
+                /// The encoder modes and the reset slave mode share the SMS
+                /// field, so the index edge is only selected as trigger (TIF)
+                /// with its interrupt enabled. Call [`Self::handle_index`] from
+                /// the timer interrupt to reset the counter to zero.
+                pub fn reset_on_index<P: EtrPin<$TIMX>>(&mut self, pin: P, polarity: Polarity) {
+                    let low = polarity == Polarity::ActiveLow;
+                    let ts = smcr_slave_bits(TriggerSource::Etrf, SlaveMode::Disabled);
+                    // TS = ETRF, ETP selects the active edge
+                    self.tim.smcr().modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(SMCR_TS_MASK | (1 << 15))) | ts | ((low as u32) << 15))
+                    });
+                    pin.setup();
+                    self.tim.sr().modify(|_, w| w.tif().clear_bit());
+                    self.tim.dier().modify(|_, w| w.tie().set_bit());
+                }
+
+                /// Resets the counter to zero if the index pulse arrived since
+                /// the last call, returns true in that case
+                pub fn handle_index(&mut self) -> bool {
+                    if self.tim.sr().read().tif().bit_is_clear() {
+                        return false;
+                    }
+                    self.tim.sr().modify(|_, w| w.tif().clear_bit());
+                    self.tim.cnt().reset();
+                    true
+                }
+
                 pub fn direction(&self) -> Direction {
                     if self.tim.cr1().read().dir().bit_is_clear() {
                         Direction::Upcounting
@@ -0,0 +1,54 @@
+//! # Servo control
+use crate::time::Hertz;
+use embedded_hal::pwm::SetDutyCycle;
+
+/// Servo refresh rate
+pub const SERVO_FREQ: Hertz = Hertz::from_raw(50);
+
+/// Servo refresh period in microseconds
+const SERVO_PERIOD_US: u32 = 1_000_000 / 50;
+
+/// Hobby servo driven by a PWM channel running at `SERVO_FREQ`
+pub struct Servo<PIN> {
+    pin: PIN,
+    min_pulse: u16,
+    max_pulse: u16,
+}
+
+impl<PIN: SetDutyCycle> Servo<PIN> {
+    /// Wraps a PWM channel using the standard 1.0 – 2.0 ms pulse range
+    pub fn new(pin: PIN) -> Self {
+        Self::with_range(pin, 1_000, 2_000)
+    }
+
+    /// Wraps a PWM channel using a custom pulse range in microseconds
+    pub fn with_range(pin: PIN, min_pulse: u16, max_pulse: u16) -> Self {
+        assert!(min_pulse < max_pulse && (max_pulse as u32) < SERVO_PERIOD_US);
+        Servo {
+            pin,
+            min_pulse,
+            max_pulse,
+        }
+    }
+
+    /// Sets the pulse width in microseconds, clamped to the configured range
+    pub fn set_pulse_us(&mut self, pulse: u16) -> Result<(), PIN::Error> {
+        let pulse = pulse.clamp(self.min_pulse, self.max_pulse) as u32;
+        let max_duty = self.pin.max_duty_cycle() as u32;
+        let duty = pulse * max_duty / SERVO_PERIOD_US;
+        self.pin.set_duty_cycle(duty as u16)
+    }
+
+    /// Sets the servo angle in degrees (0 – 180)
+    pub fn set_angle(&mut self, degrees: f32) -> Result<(), PIN::Error> {
+        let degrees = degrees.clamp(0.0, 180.0);
+        let span = (self.max_pulse - self.min_pulse) as f32;
+        let pulse = self.min_pulse + (span * degrees / 180.0) as u16;
+        self.set_pulse_us(pulse)
+    }
+
+    /// Releases the PWM channel
+    pub fn release(self) -> PIN {
+        self.pin
+    }
+}
@@ -0,0 +1,113 @@
+//! # Software timers
+//!
+//! Several periodic deadlines serviced from the update interrupt of a single
+//! hardware timer. Start the base timer with the tick period, then call
+//! [`SoftTimers::tick`] from its interrupt handler:
+//!
+//! ```ignore
+//! let mut soft = SoftTimers::<4>::new(1.millis());
+//! soft.start(0, 10.millis());
+//! soft.start(1, 250.millis());
+//! timer.start(1.millis());
+//! timer.listen();
+//!
+//! // in the timer interrupt
+//! timer.clear_irq();
+//! for slot in soft.tick() {
+//!     // slot expired
+//! }
+//! ```
+use crate::time::MicroSecond;
+
+#[derive(Copy, Clone)]
+struct Slot {
+    period: u32,
+    remaining: u32,
+}
+
+/// Up to `N` periodic software timers (N <= 32) driven by one base tick
+pub struct SoftTimers<const N: usize> {
+    tick: MicroSecond,
+    slots: [Option<Slot>; N],
+}
+
+impl<const N: usize> SoftTimers<N> {
+    /// Creates the timer set for a base timer running at the `tick` period
+    pub fn new(tick: MicroSecond) -> Self {
+        assert!(N <= 32 && tick.ticks() > 0);
+        SoftTimers {
+            tick,
+            slots: [None; N],
+        }
+    }
+
+    /// Returns the base tick period
+    pub fn tick_period(&self) -> MicroSecond {
+        self.tick
+    }
+
+    /// Starts `slot` with `period`, rounded up to a whole number of ticks
+    pub fn start(&mut self, slot: usize, period: MicroSecond) {
+        let period = period.ticks().div_ceil(self.tick.ticks()).max(1);
+        self.slots[slot] = Some(Slot {
+            period,
+            remaining: period,
+        });
+    }
+
+    /// Stops `slot`
+    pub fn stop(&mut self, slot: usize) {
+        self.slots[slot] = None;
+    }
+
+    /// Returns true if `slot` is running
+    pub fn is_running(&self, slot: usize) -> bool {
+        self.slots[slot].is_some()
+    }
+
+    /// Advances all running slots by one tick and returns the ones that expired.
+    /// Expired slots are reloaded with their period.
+    pub fn tick(&mut self) -> Expired {
+        let mut expired = 0;
+        for (idx, slot) in self.slots.iter_mut().enumerate() {
+            if let Some(slot) = slot {
+                slot.remaining -= 1;
+                if slot.remaining == 0 {
+                    slot.remaining = slot.period;
+                    expired |= 1 << idx;
+                }
+            }
+        }
+        Expired(expired)
+    }
+}
+
+/// Set of slots that expired on a tick, iterating over the slot indices
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Expired(u32);
+
+impl Expired {
+    /// Returns true if `slot` expired
+    pub fn contains(&self, slot: usize) -> bool {
+        slot < 32 && self.0 & (1 << slot) != 0
+    }
+
+    /// Returns true if no slot expired
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Iterator for Expired {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let slot = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(slot)
+    }
+}
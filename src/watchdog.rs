@@ -45,6 +45,11 @@ pub trait IWDGExt {
 }
 
 impl IndependedWatchdog {
+    /// Stops the watchdog counter while the core is halted by a debugger
+    pub fn freeze_on_debug(&mut self, rcc: &mut Rcc, freeze: bool) {
+        rcc.freeze_on_debug(false, 12, freeze);
+    }
+
     pub fn release(self) -> IWDG {
         self.iwdg
     }
@@ -94,6 +99,11 @@ impl WindowWatchdog {
         self.wwdg.cfr().write(|w| w.ewi().clear_bit());
     }
 
+    /// Stops the watchdog counter while the core is halted by a debugger
+    pub fn freeze_on_debug(&mut self, rcc: &mut Rcc, freeze: bool) {
+        rcc.freeze_on_debug(false, 11, freeze);
+    }
+
     pub fn release(self) -> WWDG {
         self.wwdg
     }